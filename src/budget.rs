@@ -0,0 +1,262 @@
+use std::{
+    fmt,
+    mem,
+    collections::{
+        VecDeque,
+    },
+    sync::{
+        Arc,
+        Weak,
+        Mutex,
+        atomic::{
+            Ordering,
+            AtomicU64,
+            AtomicUsize,
+        },
+    },
+    time::{
+        Instant,
+    },
+};
+
+use crate::{
+    PoolHead,
+};
+
+/// Memory footprint of a pooled value charged to `MemoryBudget`.
+pub trait Weight {
+    fn weight(&self) -> usize;
+}
+
+impl<T> Weight for Vec<T> {
+    fn weight(&self) -> usize {
+        self.capacity() * mem::size_of::<T>()
+    }
+}
+
+impl<T> Weight for VecDeque<T> {
+    fn weight(&self) -> usize {
+        self.capacity() * mem::size_of::<T>()
+    }
+}
+
+impl Weight for String {
+    fn weight(&self) -> usize {
+        self.capacity()
+    }
+}
+
+/// Limit on total weight of idle values shared by several pools. When a returned value does not
+/// fit, idle values are evicted from the least recently used pool first, and if nothing is left
+/// to evict the returned value is dropped.
+#[derive(Clone)]
+pub struct MemoryBudget {
+    inner: Arc<Budget>,
+}
+
+struct Budget {
+    limit: usize,
+    used: AtomicUsize,
+    created_at: Instant,
+    pools: Mutex<Vec<Weak<dyn Budgeted>>>,
+}
+
+impl fmt::Debug for MemoryBudget {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("MemoryBudget")
+            .field("limit", &self.limit())
+            .field("used", &self.used())
+            .finish()
+    }
+}
+
+impl MemoryBudget {
+    pub fn new(limit: usize) -> MemoryBudget {
+        MemoryBudget {
+            inner: Arc::new(Budget {
+                limit,
+                used: AtomicUsize::new(0),
+                created_at: Instant::now(),
+                pools: Mutex::new(Vec::new()),
+            }),
+        }
+    }
+
+    pub fn limit(&self) -> usize {
+        self.inner.limit
+    }
+
+    /// Total weight of idle values currently kept by all attached pools.
+    pub fn used(&self) -> usize {
+        self.inner.used.load(Ordering::Relaxed)
+    }
+
+    fn attach(&self, pool_head: Weak<dyn Budgeted>) {
+        let mut pools = self.inner.pools.lock().unwrap();
+        pools.retain(|pool| pool.strong_count() > 0);
+        pools.push(pool_head);
+    }
+
+    fn now(&self) -> u64 {
+        self.inner.created_at.elapsed().as_nanos() as u64
+    }
+
+    fn charge(&self, weight: usize) -> bool {
+        if weight > self.inner.limit {
+            return false;
+        }
+        loop {
+            let charged = self.inner.used
+                .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |used| if used + weight <= self.inner.limit { Some(used + weight) } else { None })
+                .is_ok();
+            if charged {
+                return true;
+            }
+            if !self.evict_least_recently_used() {
+                return false;
+            }
+        }
+    }
+
+    fn credit(&self, weight: usize) {
+        self.inner.used.fetch_sub(weight, Ordering::Relaxed);
+    }
+
+    fn evict_least_recently_used(&self) -> bool {
+        let mut pools: Vec<_> = {
+            let mut pools = self.inner.pools.lock().unwrap();
+            pools.retain(|pool| pool.strong_count() > 0);
+            pools.iter().filter_map(Weak::upgrade).collect()
+        };
+        pools.sort_by_key(|pool| pool.last_used());
+        pools.iter().any(|pool| pool.evict_idle())
+    }
+}
+
+pub(crate) trait Budgeted: Send + Sync {
+    fn last_used(&self) -> u64;
+
+    /// Drops one idle value, returns `false` if there was none.
+    fn evict_idle(&self) -> bool;
+}
+
+impl<T> Budgeted for PoolHead<T> where T: Send + Sync {
+    fn last_used(&self) -> u64 {
+        self.params.memory_budget.as_ref()
+            .map_or(0, |member| member.last_used.load(Ordering::Relaxed))
+    }
+
+    fn evict_idle(&self) -> bool {
+        self.pop_idle_largest().is_some()
+    }
+}
+
+/// Pool membership in a `MemoryBudget`.
+pub(crate) struct Member {
+    budget: MemoryBudget,
+    last_used: AtomicU64,
+}
+
+impl fmt::Debug for Member {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Member")
+            .field("budget", &self.budget)
+            .field("last_used", &self.last_used)
+            .finish()
+    }
+}
+
+impl Member {
+    pub(crate) fn new(budget: MemoryBudget) -> Member {
+        Member {
+            budget,
+            last_used: AtomicU64::new(0),
+        }
+    }
+
+    /// `pool_head` is made with `Params::register`.
+    pub(crate) fn attach(&self, pool_head: Weak<dyn Budgeted>) {
+        self.budget.attach(pool_head);
+    }
+
+    pub(crate) fn touch(&self) {
+        self.last_used.store(self.budget.now(), Ordering::Relaxed);
+    }
+
+    pub(crate) fn charge(&self, weight: usize) -> bool {
+        self.budget.charge(weight)
+    }
+
+    pub(crate) fn credit(&self, weight: usize) {
+        self.budget.credit(weight);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::MemoryBudget;
+
+    use crate::{
+        pool::Pool,
+        bytes::BytesPool,
+    };
+
+    #[test]
+    fn shared_between_bytes_pools() {
+        let budget = MemoryBudget::new(1000);
+        let pool_a = BytesPool::builder().memory_budget(budget.clone()).stats().build();
+        let pool_b = BytesPool::builder().memory_budget(budget.clone()).stats().build();
+
+        let mut bytes = pool_a.lend();
+        bytes.reserve_exact(600);
+        drop(bytes);
+        assert_eq!(budget.used(), 600);
+
+        // pool a is the least recently used one, so its buffer makes room
+        let mut bytes = pool_b.lend();
+        bytes.reserve_exact(600);
+        drop(bytes);
+        assert_eq!(budget.used(), 600);
+        assert_eq!(pool_a.stats().unwrap().idle, 0);
+        assert_eq!(pool_b.stats().unwrap().idle_weight, 600);
+
+        // does not fit even into an empty budget
+        let mut bytes = pool_a.lend();
+        bytes.reserve_exact(2000);
+        drop(bytes);
+        assert_eq!(budget.used(), 600);
+        assert_eq!(pool_a.stats().unwrap().idle, 0);
+
+        pool_b.clear();
+        assert_eq!(budget.used(), 0);
+
+        // the largest idle buffer is evicted first
+        let buffers: Vec<_> = [16, 600, 200]
+            .into_iter()
+            .map(|capacity| pool_a.try_lend(capacity).unwrap())
+            .collect();
+        drop(buffers);
+        assert_eq!(budget.used(), 816);
+        drop(pool_b.try_lend(300).unwrap());
+        assert_eq!(budget.used(), 516);
+        assert_eq!(pool_a.stats().unwrap().idle_weight, 216);
+    }
+
+    #[test]
+    fn generic_pool_weight() {
+        let budget = MemoryBudget::new(64);
+        let pool = Pool::builder().memory_budget(budget.clone()).build();
+        let value_a = pool.lend(|| Vec::<u64>::with_capacity(4));
+        let value_b = pool.lend(|| Vec::<u64>::with_capacity(4));
+        let value_c = pool.lend(|| Vec::<u64>::with_capacity(4));
+        drop(value_a);
+        drop(value_b);
+        assert_eq!(budget.used(), 64);
+        // evicts an idle value of the same pool
+        drop(value_c);
+        assert_eq!(budget.used(), 64);
+
+        drop(pool);
+        assert_eq!(budget.used(), 0);
+    }
+}
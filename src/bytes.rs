@@ -3,6 +3,9 @@ use std::{
         Deref,
         DerefMut,
     },
+    collections::{
+        TryReserveError,
+    },
     hash::{
         Hash,
         Hasher,
@@ -15,30 +18,79 @@ use std::{
 
 use crate::{
     pool,
+    stats::PoolStats,
+    budget::MemoryBudget,
+    pressure::PurgePolicy,
     Shared,
     Unique,
     WeakShared,
 };
 
+#[cfg(feature = "metrics")]
+use crate::metrics::PoolMetrics;
+
+#[cfg(feature = "leak-detection")]
+use crate::leak::{
+    LeakAction,
+    OutstandingHandle,
+};
+
+// idle buffers are kept in separate stacks by power of two capacity class, empty buffers have
+// a class of their own
+const SIZE_CLASSES: usize = usize::BITS as usize + 1;
+
 type BytesInner = Shared<Vec<u8>>;
 type BytesMutInner = Unique<Vec<u8>>;
 type BytesWeakInner = WeakShared<Vec<u8>>;
 
-#[derive(PartialEq, Hash, Debug)]
+/// What `BytesMut::freeze` does with spare capacity of the buffer.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Default, Debug)]
+pub enum FreezePolicy {
+    /// Reallocates the buffer to fit its contents exactly.
+    #[default]
+    ShrinkToFit,
+    /// Keeps the allocation as it is, so the buffer returns to the pool with full capacity.
+    KeepCapacity,
+}
+
+#[derive(Debug)]
 pub struct BytesMut {
     unique: BytesMutInner,
+    freeze_policy: FreezePolicy,
+}
+
+impl PartialEq for BytesMut {
+    fn eq(&self, other: &BytesMut) -> bool {
+        self.unique == other.unique
+    }
+}
+
+impl Hash for BytesMut {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.unique.hash(state);
+    }
 }
 
 impl BytesMut {
     pub fn new_detached(value: Vec<u8>) -> Self {
-        Self { unique: BytesMutInner::new_detached(value), }
+        Self { unique: BytesMutInner::new_detached(value), freeze_policy: FreezePolicy::default(), }
+    }
+
+    pub fn freeze_policy(&self) -> FreezePolicy {
+        self.freeze_policy
+    }
+
+    pub fn set_freeze_policy(&mut self, freeze_policy: FreezePolicy) {
+        self.freeze_policy = freeze_policy;
     }
 
     pub fn freeze(mut self) -> Bytes {
-        self.unique.shrink_to_fit();
+        if self.freeze_policy == FreezePolicy::ShrinkToFit {
+            self.unique.shrink_to_fit();
+        }
         let inner = self.unique.freeze();
         let offset_to = inner.len();
-        Bytes { inner, offset_from: 0, offset_to, }
+        Bytes { inner, offset_from: 0, offset_to, freeze_policy: self.freeze_policy, }
     }
 
     pub fn freeze_range<R>(self, range: R) -> Bytes where R: RangeBounds<usize> {
@@ -95,6 +147,7 @@ pub struct Bytes {
     inner: BytesInner,
     offset_from: usize,
     offset_to: usize,
+    freeze_policy: FreezePolicy,
 }
 
 #[derive(Clone, Debug)]
@@ -102,6 +155,7 @@ pub struct BytesWeak {
     inner: BytesWeakInner,
     offset_from: usize,
     offset_to: usize,
+    freeze_policy: FreezePolicy,
 }
 
 impl AsRef<[u8]> for Bytes {
@@ -140,6 +194,21 @@ impl Bytes {
             inner: self.inner.downgrade(),
             offset_from: self.offset_from,
             offset_to: self.offset_to,
+            freeze_policy: self.freeze_policy,
+        }
+    }
+
+    /// Turns back into `BytesMut` if this is the only strong reference to the buffer, otherwise
+    /// returns `self` unchanged. The buffer is cut down to the range of this `Bytes` in place.
+    pub fn try_into_mut(self) -> Result<BytesMut, Bytes> {
+        match self.inner.try_into_unique() {
+            Ok(mut unique) => {
+                unique.truncate(self.offset_to);
+                unique.drain(.. self.offset_from);
+                Ok(BytesMut { unique, freeze_policy: self.freeze_policy, })
+            },
+            Err(inner) =>
+                Err(Bytes { inner, ..self }),
         }
     }
 
@@ -210,6 +279,7 @@ impl Bytes {
                 inner: self.inner.clone(),
                 offset_from,
                 offset_to,
+                freeze_policy: self.freeze_policy,
             }
         }
     }
@@ -222,6 +292,7 @@ impl BytesWeak {
                 inner: arc,
                 offset_from: self.offset_from,
                 offset_to: self.offset_to,
+                freeze_policy: self.freeze_policy,
             })
     }
 }
@@ -230,12 +301,13 @@ impl BytesWeak {
 #[derive(Clone, Debug)]
 pub struct BytesPool {
     kind: BytesPoolKind,
+    freeze_policy: FreezePolicy,
 }
 
 #[derive(Clone, Debug)]
 enum BytesPoolKind {
     Attached {
-        pool: pool::Pool<Vec<u8>>,
+        pool: pool::FactoryPool<Vec<u8>, fn() -> Vec<u8>>,
     },
     Detached,
 }
@@ -248,28 +320,233 @@ impl Default for BytesPool {
 
 impl BytesPool {
     pub fn new() -> BytesPool {
-        BytesPool {
-            kind: BytesPoolKind::Attached {
-                pool: pool::Pool::new(),
-            },
-        }
+        BytesPoolBuilder::new().build()
+    }
+
+    pub fn builder() -> BytesPoolBuilder {
+        BytesPoolBuilder::new()
+    }
+
+    /// Creates a pool listed by `registry::snapshot` under `name`.
+    pub fn named<S>(name: S) -> BytesPool where S: Into<String> {
+        BytesPoolBuilder::new().name(name).build()
     }
 
     pub fn new_detached() -> BytesPool {
         BytesPool {
             kind: BytesPoolKind::Detached,
+            freeze_policy: FreezePolicy::default(),
         }
     }
 
     pub fn lend(&self) -> BytesMut {
         match &self.kind {
             BytesPoolKind::Attached { pool, } => {
-                let mut bytes = pool.lend(Vec::new);
-                bytes.clear();
-                BytesMut { unique: bytes, }
+                let bytes = pool.lend();
+                self.wrap(bytes)
+            },
+            BytesPoolKind::Detached =>
+                self.wrap(BytesMutInner::new_detached(Vec::new())),
+        }
+    }
+
+    /// Lends a buffer with room for at least `capacity` bytes, taking the smallest suitable one
+    /// from idle buffers.
+    pub fn lend_with_capacity(&self, capacity: usize) -> BytesMut {
+        match &self.kind {
+            BytesPoolKind::Attached { pool, } => {
+                let bytes = pool.pool().lend_from_class(min_capacity_class(capacity), || Vec::with_capacity(capacity));
+                self.wrap(bytes)
             },
             BytesPoolKind::Detached =>
-                BytesMut::new_detached(Vec::new()),
+                self.wrap(BytesMutInner::new_detached(Vec::with_capacity(capacity))),
+        }
+    }
+
+    pub fn stats(&self) -> Option<PoolStats> {
+        match &self.kind {
+            BytesPoolKind::Attached { pool, } =>
+                pool.pool().stats(),
+            BytesPoolKind::Detached =>
+                None,
+        }
+    }
+
+    #[cfg(feature = "metrics")]
+    pub fn publish_metrics(&self, metrics: &PoolMetrics) {
+        if let Some(stats) = self.stats() {
+            metrics.publish(&stats, true);
+        }
+    }
+
+    #[cfg(feature = "leak-detection")]
+    pub fn outstanding_report(&self) -> Vec<OutstandingHandle> {
+        match &self.kind {
+            BytesPoolKind::Attached { pool, } =>
+                pool.pool().outstanding_report(),
+            BytesPoolKind::Detached =>
+                Vec::new(),
+        }
+    }
+
+    /// Drops idle buffers, the largest ones first, until their total capacity is at most
+    /// `total_capacity` bytes. Returns the number of buffers dropped.
+    pub fn trim_to_bytes(&self, total_capacity: usize) -> usize {
+        match &self.kind {
+            BytesPoolKind::Attached { pool, } =>
+                pool.pool().trim_weight_to(total_capacity),
+            BytesPoolKind::Detached =>
+                0,
+        }
+    }
+
+    /// Drops all idle buffers. Returns the number of buffers dropped.
+    pub fn clear(&self) -> usize {
+        match &self.kind {
+            BytesPoolKind::Attached { pool, } =>
+                pool.pool().clear(),
+            BytesPoolKind::Detached =>
+                0,
+        }
+    }
+
+    /// Same as `lend_with_capacity`, but reports allocation failure instead of aborting.
+    pub fn try_lend(&self, capacity: usize) -> Result<BytesMut, TryReserveError> {
+        let make_value = || -> Result<Vec<u8>, TryReserveError> {
+            let mut bytes = Vec::new();
+            bytes.try_reserve(capacity)?;
+            Ok(bytes)
+        };
+        match &self.kind {
+            BytesPoolKind::Attached { pool, } => {
+                let bytes = pool.pool().try_lend_from_class(min_capacity_class(capacity), make_value)?;
+                Ok(self.wrap(bytes))
+            },
+            BytesPoolKind::Detached =>
+                Ok(self.wrap(BytesMutInner::new_detached(make_value()?))),
+        }
+    }
+
+    fn wrap(&self, unique: BytesMutInner) -> BytesMut {
+        BytesMut { unique, freeze_policy: self.freeze_policy, }
+    }
+}
+
+fn capacity_class(capacity: usize) -> usize {
+    capacity.checked_ilog2().map_or(0, |class| class as usize + 1)
+}
+
+// the smallest class with all buffers having room for `capacity` bytes
+fn min_capacity_class(capacity: usize) -> usize {
+    match capacity {
+        0 =>
+            0,
+        capacity =>
+            capacity.checked_next_power_of_two()
+                .map_or(SIZE_CLASSES, |capacity| capacity.trailing_zeros() as usize + 1),
+    }
+}
+
+#[derive(Clone, Default, Debug)]
+pub struct BytesPoolBuilder {
+    name: Option<String>,
+    max_buffer_capacity: Option<usize>,
+    stats: bool,
+    memory_budget: Option<MemoryBudget>,
+    purge_policy: PurgePolicy,
+    freeze_policy: FreezePolicy,
+    #[cfg(feature = "leak-detection")]
+    lend_backtraces: bool,
+    #[cfg(feature = "leak-detection")]
+    on_leak: LeakAction,
+}
+
+impl BytesPoolBuilder {
+    pub fn new() -> BytesPoolBuilder {
+        BytesPoolBuilder::default()
+    }
+
+    /// Registers the pool in `registry` under `name`.
+    pub fn name<S>(mut self, name: S) -> Self where S: Into<String> {
+        self.name = Some(name.into());
+        self
+    }
+
+    /// Buffers which have grown beyond `max_buffer_capacity` are dropped instead of being
+    /// returned back to the pool.
+    pub fn max_buffer_capacity(mut self, max_buffer_capacity: usize) -> Self {
+        self.max_buffer_capacity = Some(max_buffer_capacity);
+        self
+    }
+
+    pub fn stats(mut self) -> Self {
+        self.stats = true;
+        self
+    }
+
+    pub fn purge_policy(mut self, purge_policy: PurgePolicy) -> Self {
+        self.purge_policy = purge_policy;
+        self
+    }
+
+    /// Sets `FreezePolicy` of lent buffers, `FreezePolicy::ShrinkToFit` by default.
+    pub fn freeze_policy(mut self, freeze_policy: FreezePolicy) -> Self {
+        self.freeze_policy = freeze_policy;
+        self
+    }
+
+    /// Charges capacity of idle buffers to `budget`, which may be shared with other pools.
+    pub fn memory_budget(mut self, budget: MemoryBudget) -> Self {
+        self.memory_budget = Some(budget);
+        self
+    }
+
+    #[cfg(feature = "leak-detection")]
+    pub fn lend_backtraces(mut self) -> Self {
+        self.lend_backtraces = true;
+        self
+    }
+
+    #[cfg(feature = "leak-detection")]
+    pub fn on_leak(mut self, on_leak: LeakAction) -> Self {
+        self.on_leak = on_leak;
+        self
+    }
+
+    pub fn build(self) -> BytesPool {
+        let mut pool_builder = pool::Pool::builder()
+            .recycling()
+            .weigh(Vec::capacity)
+            .size_classes(SIZE_CLASSES, |bytes: &Vec<u8>| capacity_class(bytes.capacity()))
+            .purge_policy(self.purge_policy);
+        if self.stats {
+            pool_builder = pool_builder.stats();
+        }
+        if let Some(budget) = self.memory_budget {
+            pool_builder = pool_builder.memory_budget(budget);
+        }
+        if let Some(max_buffer_capacity) = self.max_buffer_capacity {
+            pool_builder = pool_builder
+                .return_filter(move |bytes: &Vec<u8>| bytes.capacity() <= max_buffer_capacity);
+        }
+        #[cfg(feature = "leak-detection")]
+        {
+            if self.lend_backtraces {
+                pool_builder = pool_builder.lend_backtraces();
+            }
+            pool_builder = pool_builder.on_leak(self.on_leak);
+        }
+        let pool = match self.name {
+            Some(name) =>
+                pool_builder.build_named(name),
+            None =>
+                pool_builder.build(),
+        };
+        BytesPool {
+            kind: BytesPoolKind::Attached {
+                pool: pool::FactoryPool::new(pool, Vec::new),
+            },
+            freeze_policy: self.freeze_policy,
         }
     }
 }
@@ -278,6 +555,18 @@ impl BytesPool {
 mod tests {
     use super::{
         BytesMut,
+        BytesPool,
+        BytesPoolKind,
+        FreezePolicy,
+    };
+
+    use crate::{
+        registry,
+        pressure::{
+            PressureLevel,
+            PurgePolicy,
+            Retention,
+        },
     };
 
     #[test]
@@ -429,4 +718,184 @@ mod tests {
         let bytes_cloned = bytes.clone_subslice(subslice);
         assert_eq!(&*bytes_cloned, &[]);
     }
+
+    #[test]
+    fn max_buffer_capacity_00() {
+        let pool = BytesPool::builder()
+            .max_buffer_capacity(16)
+            .build();
+
+        let mut bytes_a = pool.lend();
+        bytes_a.reserve_exact(8);
+        let capacity_a = bytes_a.capacity();
+        let mut bytes_b = pool.lend();
+        bytes_b.reserve_exact(1024);
+        drop(bytes_a);
+        drop(bytes_b);
+
+        let bytes = pool.lend();
+        assert_eq!(bytes.capacity(), capacity_a);
+        let bytes = pool.lend();
+        assert_eq!(bytes.capacity(), 0);
+    }
+
+    #[test]
+    fn try_lend_00() {
+        let pool = BytesPool::new();
+
+        let bytes = pool.try_lend(64).unwrap();
+        assert!(bytes.capacity() >= 64);
+        drop(bytes);
+
+        assert!(pool.try_lend(usize::MAX).is_err());
+        let bytes = pool.try_lend(16).unwrap();
+        assert!(bytes.capacity() >= 64);
+
+        // an idle buffer of a suitable size class is reused instead of growing an empty one
+        let empty = pool.lend();
+        let large = pool.try_lend(1 << 20).unwrap();
+        let large_ptr = large.as_ptr();
+        drop((empty, large));
+        assert_eq!(pool.try_lend(1 << 20).unwrap().as_ptr(), large_ptr);
+        drop(bytes);
+
+        assert!(BytesPool::new_detached().try_lend(usize::MAX).is_err());
+    }
+
+    #[test]
+    fn trim_to_bytes_00() {
+        let pool = BytesPool::new();
+
+        let buffers: Vec<_> = [16, 32, 64]
+            .into_iter()
+            .map(|capacity| pool.try_lend(capacity).unwrap())
+            .collect();
+        let capacities: Vec<_> = buffers.iter().map(|bytes| bytes.capacity()).collect();
+        drop(buffers);
+
+        // idle buffers are in separate size classes, the largest ones are dropped first
+        assert_eq!(pool.trim_to_bytes(capacities[2] + capacities[1]), 1);
+        assert_eq!(pool.trim_to_bytes(capacities[2] + capacities[1]), 0);
+        let bytes_a = pool.lend();
+        let bytes_b = pool.lend();
+        assert_eq!(bytes_a.capacity(), capacities[0]);
+        assert_eq!(bytes_b.capacity(), capacities[1]);
+        drop(bytes_a);
+        drop(bytes_b);
+
+        assert_eq!(pool.clear(), 2);
+        assert_eq!(pool.lend().capacity(), 0);
+        assert_eq!(BytesPool::new_detached().trim_to_bytes(0), 0);
+    }
+
+    #[test]
+    fn lend_with_capacity_00() {
+        let pool = BytesPool::builder().stats().build();
+
+        let buffers: Vec<_> = [64, 4096, 1 << 20]
+            .into_iter()
+            .map(|capacity| pool.lend_with_capacity(capacity))
+            .collect();
+        assert!(buffers.iter().zip([64, 4096, 1 << 20]).all(|(bytes, capacity)| bytes.capacity() >= capacity));
+        let capacities: Vec<_> = buffers.iter().map(|bytes| bytes.capacity()).collect();
+        drop(buffers);
+
+        let bytes_a = pool.lend_with_capacity(1000);
+        assert_eq!(bytes_a.capacity(), capacities[1]);
+        let bytes_b = pool.lend_with_capacity(100);
+        assert_eq!(bytes_b.capacity(), capacities[2]);
+        let bytes_c = pool.lend();
+        assert_eq!(bytes_c.capacity(), capacities[0]);
+        let bytes_d = pool.lend_with_capacity(2 << 20);
+        assert!(bytes_d.capacity() >= 2 << 20);
+        assert_eq!(pool.stats().unwrap().hits, 3);
+
+        // a grown buffer goes back to its new size class
+        let mut bytes_c = bytes_c;
+        bytes_c.reserve_exact(1 << 16);
+        let capacity = bytes_c.capacity();
+        drop(bytes_c);
+        assert_eq!(pool.lend_with_capacity(1 << 16).capacity(), capacity);
+
+        assert!(BytesPool::new_detached().lend_with_capacity(10).capacity() >= 10);
+        assert!(format!("{:?}", pool.lend()).contains("PoolHead { id: "));
+
+        // empty buffers are not lent for a non zero capacity
+        let pool = BytesPool::new();
+        drop(pool.lend());
+        let bytes = pool.lend_with_capacity(1);
+        assert!(bytes.capacity() >= 1);
+        drop(bytes);
+        assert_eq!(pool.lend_with_capacity(0).capacity(), 0);
+        assert!(pool.lend_with_capacity(1).capacity() >= 1);
+    }
+
+    #[test]
+    fn freeze_policy_00() {
+        let pool = BytesPool::builder().freeze_policy(FreezePolicy::KeepCapacity).build();
+        let mut bytes = pool.lend_with_capacity(1024);
+        let capacity = bytes.capacity();
+        bytes.extend_from_slice(&[0, 1, 2]);
+        let frozen = bytes.freeze();
+        assert_eq!(&*frozen, &[0, 1, 2]);
+        drop(frozen);
+        assert_eq!(pool.lend().capacity(), capacity);
+
+        let mut bytes = pool.lend();
+        bytes.set_freeze_policy(FreezePolicy::ShrinkToFit);
+        bytes.extend_from_slice(&[0, 1, 2]);
+        drop(bytes.freeze());
+        assert_eq!(pool.lend().capacity(), 3);
+
+        assert_eq!(BytesPool::new().lend().freeze_policy(), FreezePolicy::ShrinkToFit);
+    }
+
+    #[test]
+    fn try_into_mut_00() {
+        let pool = BytesPool::builder().freeze_policy(FreezePolicy::KeepCapacity).build();
+        let mut bytes = pool.lend_with_capacity(64);
+        bytes.extend_from_slice(&[0, 1, 2, 3, 4]);
+        let capacity = bytes.capacity();
+
+        let frozen = bytes.freeze();
+        let frozen_sub = frozen.subrange(1 ..= 3);
+        let frozen = frozen.try_into_mut().unwrap_err();
+        drop(frozen);
+
+        let mut bytes = frozen_sub.try_into_mut().unwrap();
+        assert_eq!(&***bytes, &[1, 2, 3]);
+        assert_eq!(bytes.capacity(), capacity);
+        assert_eq!(bytes.freeze_policy(), FreezePolicy::KeepCapacity);
+        bytes.push(4);
+        assert_eq!(&*bytes.freeze(), &[1, 2, 3, 4]);
+    }
+
+    #[test]
+    fn purge_00() {
+        let pool_id = |pool: &BytesPool| match &pool.kind {
+            BytesPoolKind::Attached { pool, } =>
+                pool.pool().id(),
+            BytesPoolKind::Detached =>
+                unreachable!(),
+        };
+        let pool = BytesPool::new();
+        let opted_out = BytesPool::builder().purge_policy(PurgePolicy::opt_out()).build();
+        for pool in [&pool, &opted_out] {
+            drop((pool.lend_with_capacity(16), pool.lend_with_capacity(32)));
+        }
+
+        let purged = registry::purge_where(PressureLevel::Critical, |id| id == pool_id(&pool) || id == pool_id(&opted_out));
+        assert_eq!(purged, 2);
+        assert_eq!(pool.clear(), 0);
+        assert_eq!(opted_out.clear(), 2);
+
+        // the largest buffers are dropped first
+        let keep_one = Retention::KeepAtMost(1);
+        let pool = BytesPool::builder()
+            .purge_policy(PurgePolicy { mild: keep_one, moderate: keep_one, critical: keep_one, })
+            .build();
+        drop((pool.lend_with_capacity(16), pool.lend_with_capacity(1 << 20)));
+        assert_eq!(registry::purge_where(PressureLevel::Mild, |id| id == pool_id(&pool)), 1);
+        assert!(pool.lend().capacity() < 1 << 20);
+    }
 }
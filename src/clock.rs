@@ -0,0 +1,19 @@
+use std::{
+    time::{
+        Instant,
+    },
+};
+
+/// Source of time for pool policies, may be replaced for testing purposes.
+pub trait Clock: Send + Sync {
+    fn now(&self) -> Instant;
+}
+
+#[derive(Clone, Copy, Default, Debug)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+}
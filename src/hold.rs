@@ -0,0 +1,66 @@
+use std::{
+    time::{
+        Duration,
+    },
+    sync::{
+        atomic::{
+            Ordering,
+            AtomicU64,
+        },
+    },
+};
+
+/// Upper bounds of `HoldHistogram` buckets, the last bucket holds everything above.
+pub const HOLD_BUCKET_BOUNDS: [Duration; 7] = [
+    Duration::from_micros(10),
+    Duration::from_micros(100),
+    Duration::from_millis(1),
+    Duration::from_millis(10),
+    Duration::from_millis(100),
+    Duration::from_secs(1),
+    Duration::from_secs(10),
+];
+
+const BUCKETS: usize = HOLD_BUCKET_BOUNDS.len() + 1;
+
+/// Distribution of how long lent values have been held before they were returned, see
+/// `Pool::hold_histogram`.
+#[derive(Clone, Copy, PartialEq, Eq, Default, Debug)]
+pub struct HoldHistogram {
+    counts: [u64; BUCKETS],
+}
+
+impl HoldHistogram {
+    /// Iterates over buckets as pairs of inclusive upper bound and count, the last bucket
+    /// is unbounded.
+    pub fn buckets(&self) -> impl Iterator<Item = (Option<Duration>, u64)> + '_ {
+        HOLD_BUCKET_BOUNDS.iter()
+            .map(|&bound| Some(bound))
+            .chain(Some(None))
+            .zip(self.counts.iter().copied())
+    }
+
+    pub fn total(&self) -> u64 {
+        self.counts.iter().sum()
+    }
+}
+
+#[derive(Default, Debug)]
+pub(crate) struct HoldCounters {
+    counts: [AtomicU64; BUCKETS],
+}
+
+impl HoldCounters {
+    pub(crate) fn record(&self, held: Duration) {
+        let bucket = HOLD_BUCKET_BOUNDS.iter()
+            .position(|&bound| held <= bound)
+            .unwrap_or(BUCKETS - 1);
+        self.counts[bucket].fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn snapshot(&self) -> HoldHistogram {
+        HoldHistogram {
+            counts: std::array::from_fn(|bucket| self.counts[bucket].load(Ordering::Relaxed)),
+        }
+    }
+}
@@ -0,0 +1,121 @@
+use std::{
+    fmt,
+    backtrace::{
+        Backtrace,
+    },
+    collections::{
+        HashMap,
+    },
+    sync::{
+        Arc,
+        Mutex,
+    },
+    time::{
+        Duration,
+        Instant,
+    },
+};
+
+/// What to do when the last `Pool` handle is dropped while lent values are still out.
+#[derive(Clone, Copy, PartialEq, Eq, Default, Debug)]
+pub enum LeakAction {
+    #[default]
+    Ignore,
+    /// Report outstanding handles as a `tracing` error. Same as `Ignore` without the `tracing`
+    /// feature.
+    Log,
+    /// Panic with the list of outstanding handles. If the thread is panicking already, they are
+    /// reported as with `Log` instead.
+    Panic,
+}
+
+/// Lent value which has not been returned back yet, see `Pool::outstanding_report`.
+#[derive(Clone, Debug)]
+pub struct OutstandingHandle {
+    pub object_id: u64,
+    /// Time passed since the value has been lent.
+    pub age: Duration,
+    /// Backtrace of the `lend` call, if enabled with `PoolBuilder::lend_backtraces`.
+    pub lent_at: Option<Arc<Backtrace>>,
+}
+
+impl fmt::Display for OutstandingHandle {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "object #{} lent {:?} ago", self.object_id, self.age)?;
+        if let Some(lent_at) = &self.lent_at {
+            write!(f, " at:\n{lent_at}")?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Clone, Copy, Default, Debug)]
+pub(crate) struct Settings {
+    pub(crate) lend_backtraces: bool,
+    pub(crate) on_leak: LeakAction,
+}
+
+#[derive(Default, Debug)]
+pub(crate) struct Registry {
+    handles: Mutex<HashMap<u64, Record>>,
+}
+
+#[derive(Debug)]
+struct Record {
+    lent_at: Instant,
+    backtrace: Option<Arc<Backtrace>>,
+}
+
+impl Registry {
+    pub(crate) fn register(&self, object_id: u64, now: Instant, settings: &Settings) {
+        let backtrace = if settings.lend_backtraces {
+            Some(Arc::new(Backtrace::force_capture()))
+        } else {
+            None
+        };
+        self.handles.lock().unwrap()
+            .insert(object_id, Record { lent_at: now, backtrace, });
+    }
+
+    pub(crate) fn unregister(&self, object_id: u64) {
+        self.handles.lock().unwrap()
+            .remove(&object_id);
+    }
+
+    /// Returns outstanding handles, the oldest first.
+    pub(crate) fn report(&self, now: Instant) -> Vec<OutstandingHandle> {
+        let mut report: Vec<_> = self.handles.lock().unwrap()
+            .iter()
+            .map(|(&object_id, record)| OutstandingHandle {
+                object_id,
+                age: now.saturating_duration_since(record.lent_at),
+                lent_at: record.backtrace.clone(),
+            })
+            .collect();
+        report.sort_by(|a, b| b.age.cmp(&a.age).then(a.object_id.cmp(&b.object_id)));
+        report
+    }
+
+    pub(crate) fn pool_detached(&self, pool_id: u64, now: Instant, settings: &Settings) {
+        if settings.on_leak == LeakAction::Ignore {
+            return;
+        }
+        let report = self.report(now);
+        if report.is_empty() {
+            return;
+        }
+        let mut message = format!("alloc-pool: pool #{pool_id} dropped while {} lent values are still out", report.len());
+        for handle in &report {
+            message.push_str("\n  ");
+            message.push_str(&handle.to_string());
+        }
+        match settings.on_leak {
+            LeakAction::Panic if !std::thread::panicking() =>
+                panic!("{message}"),
+            _ => {
+                #[cfg(feature = "tracing")]
+                tracing::error!(pool_id, "{message}");
+            },
+        }
+    }
+}
@@ -1,4 +1,5 @@
 use std::{
+    fmt,
     ptr,
     mem::{
         ManuallyDrop,
@@ -8,9 +9,15 @@ use std::{
         Weak,
         atomic::{
             Ordering,
+            AtomicU64,
             AtomicBool,
+            AtomicUsize,
         },
     },
+    time::{
+        Duration,
+        Instant,
+    },
     ops::{
         Deref,
         DerefMut,
@@ -23,8 +30,35 @@ use std::{
 
 use crossbeam_epoch as epoch;
 
+macro_rules! trace_event {
+    ($message:literal $(, $field:ident = $value:expr)* $(,)?) => {{
+        #[cfg(feature = "tracing")]
+        tracing::trace!($($field = $value,)* $message);
+        #[cfg(not(feature = "tracing"))]
+        { $(let _ = &$value;)* }
+    }};
+}
+
 pub mod pool;
 pub mod bytes;
+pub mod recycle;
+pub mod clock;
+pub mod maintainer;
+pub mod stats;
+pub mod hold;
+pub mod registry;
+pub mod budget;
+pub mod pressure;
+
+#[cfg(feature = "metrics")]
+pub mod metrics;
+
+#[cfg(feature = "leak-detection")]
+pub mod leak;
+
+mod wait;
+
+pub use pressure::purge_all;
 
 #[derive(Debug)]
 pub struct Unique<T> {
@@ -56,18 +90,132 @@ impl<T> Clone for WeakShared<T> {
 #[derive(Debug)]
 struct Inner<T> {
     value: Option<T>,
+    object_id: u64,
+    // only tracked when hold times are watched, see `PoolBuilder::hold_threshold`
+    lent_at: Option<Instant>,
     pool_head: Arc<PoolHead<T>>,
 }
 
-#[derive(Debug)]
 struct PoolHead<T> {
+    id: u64,
     is_detached: AtomicBool,
-    head: epoch::Atomic<Entry<T>>,
+    // idle stacks, one per size class, see `Params::classify`
+    heads: Box<[epoch::Atomic<Entry<T>>]>,
+    idle: AtomicUsize,
+    // total weight of idle values as measured by `Params::weigh`
+    idle_weight: AtomicUsize,
+    slots: wait::Slots,
+    // number of `Pool` handles, values lent from the pool may outlive all of them
+    handles: AtomicUsize,
+    stats: Option<stats::Counters>,
+    holds: Option<hold::HoldCounters>,
+    #[cfg(feature = "leak-detection")]
+    leaks: leak::Registry,
+    created_at: Instant,
+    // nanoseconds since `created_at` when the next lazy eviction of expired entries is due
+    eviction_due: AtomicU64,
+    params: Params<T>,
+}
+
+type ReturnFilter<T> = Box<dyn Fn(&T) -> bool + Send + Sync>;
+type LongHoldHandler<T> = Box<dyn Fn(&T, Duration) + Send + Sync>;
+type Register<T> = fn(&Arc<PoolHead<T>>) -> Weak<dyn registry::Registered>;
+
+struct Params<T> {
+    name: Option<String>,
+    max_idle: Option<usize>,
+    min_idle: Option<usize>,
+    max_outstanding: Option<usize>,
+    recycle: Option<fn(&mut T)>,
+    weigh: Option<fn(&T) -> usize>,
+    // idle values are kept in `size_classes` separate stacks routed by `classify`
+    size_classes: usize,
+    classify: Option<fn(&T) -> usize>,
+    return_filter: Option<ReturnFilter<T>>,
+    idle_ttl: Option<Duration>,
+    clock: Arc<dyn clock::Clock>,
+    stats: bool,
+    hold_threshold: Option<Duration>,
+    on_long_hold: Option<LongHoldHandler<T>>,
+    hold_histogram: bool,
+    memory_budget: Option<budget::Member>,
+    purge_policy: pressure::PurgePolicy,
+    // set for pools listed in `registry`, which includes all the pools in a `MemoryBudget`
+    register: Option<Register<T>>,
+    #[cfg(feature = "leak-detection")]
+    leak_detection: leak::Settings,
+}
+
+impl<T> Default for Params<T> {
+    fn default() -> Params<T> {
+        Params {
+            name: None,
+            max_idle: None,
+            min_idle: None,
+            max_outstanding: None,
+            recycle: None,
+            weigh: None,
+            size_classes: 1,
+            classify: None,
+            return_filter: None,
+            idle_ttl: None,
+            clock: Arc::new(clock::SystemClock),
+            stats: false,
+            hold_threshold: None,
+            on_long_hold: None,
+            hold_histogram: false,
+            memory_budget: None,
+            purge_policy: pressure::PurgePolicy::default(),
+            register: None,
+            #[cfg(feature = "leak-detection")]
+            leak_detection: leak::Settings::default(),
+        }
+    }
+}
+
+impl<T> fmt::Debug for PoolHead<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("PoolHead")
+            .field("id", &self.id)
+            .field("name", &self.params.name)
+            .field("idle", &self.idle.load(Ordering::Relaxed))
+            .field("outstanding", &self.slots.outstanding())
+            .finish()
+    }
+}
+
+impl<T> fmt::Debug for Params<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut debug = f.debug_struct("Params");
+        debug
+            .field("name", &self.name)
+            .field("max_idle", &self.max_idle)
+            .field("min_idle", &self.min_idle)
+            .field("max_outstanding", &self.max_outstanding)
+            .field("recycle", &self.recycle.is_some())
+            .field("weigh", &self.weigh.is_some())
+            .field("size_classes", &self.size_classes)
+            .field("return_filter", &self.return_filter.is_some())
+            .field("idle_ttl", &self.idle_ttl)
+            .field("stats", &self.stats)
+            .field("hold_threshold", &self.hold_threshold)
+            .field("on_long_hold", &self.on_long_hold.is_some())
+            .field("hold_histogram", &self.hold_histogram)
+            .field("memory_budget", &self.memory_budget)
+            .field("purge_policy", &self.purge_policy)
+            .field("register", &self.register.is_some());
+        #[cfg(feature = "leak-detection")]
+        debug.field("leak_detection", &self.leak_detection);
+        debug.finish()
+    }
 }
 
 #[derive(Debug)]
 struct Entry<T> {
     value: ManuallyDrop<T>,
+    returned_at: Option<Instant>,
+    weight: usize,
+    object_id: u64,
     next: epoch::Atomic<Entry<T>>,
 }
 
@@ -113,6 +261,36 @@ impl<T> Shared<T> {
             inner: Arc::downgrade(&self.inner),
         }
     }
+
+    /// Returns a mutable reference to the value if there are no other `Shared` or `WeakShared`
+    /// handles to it. Otherwise lends a value from the same pool, overwrites it with a clone
+    /// and repoints this handle to it. The new value is lent even if `max_outstanding` is
+    /// reached, as waiting for a slot here could deadlock.
+    pub fn make_mut(&mut self) -> &mut T where T: Clone {
+        if Arc::get_mut(&mut self.inner).is_none() {
+            let pool_head = self.inner.pool_head.clone();
+            pool_head.slots.force_acquire();
+            let (value, object_id, hit) = match pool_head.pop_idle() {
+                Some((mut value, object_id)) => {
+                    value.clone_from(self.as_ref());
+                    (value, object_id, true)
+                },
+                None =>
+                    (self.as_ref().clone(), next_object_id(), false),
+            };
+            pool_head.lent(object_id, hit);
+            self.inner = Arc::new(Inner::new(value, object_id, pool_head));
+        }
+        Arc::get_mut(&mut self.inner).unwrap().value.as_mut().unwrap()
+    }
+
+    /// Turns back into `Unique` if this is the only strong reference to the value, otherwise
+    /// returns `self` unchanged. The value stays lent from the same pool.
+    pub fn try_into_unique(self) -> Result<Unique<T>, Shared<T>> {
+        Arc::try_unwrap(self.inner)
+            .map(|inner| Unique { inner, })
+            .map_err(|inner| Shared { inner, })
+    }
 }
 
 impl<T> WeakShared<T> {
@@ -128,25 +306,445 @@ impl<T> Unique<T> {
     }
 
     pub fn freeze(self) -> Shared<T> {
+        trace_event!("freeze", pool_id = self.inner.pool_head.id, object_id = self.inner.object_id);
         Shared {
             inner: Arc::new(self.inner),
         }
     }
 }
 
+static NEXT_POOL_ID: AtomicU64 = AtomicU64::new(0);
+static NEXT_OBJECT_ID: AtomicU64 = AtomicU64::new(0);
+
+fn next_object_id() -> u64 {
+    NEXT_OBJECT_ID.fetch_add(1, Ordering::Relaxed)
+}
+
 impl<T> Inner<T> {
-    fn new(value: T, pool_head: Arc<PoolHead<T>>) -> Inner<T> {
-        Inner { value: Some(value), pool_head, }
+    fn new(value: T, object_id: u64, pool_head: Arc<PoolHead<T>>) -> Inner<T> {
+        let lent_at = pool_head.lent_at();
+        Inner { value: Some(value), object_id, lent_at, pool_head, }
     }
 
     fn new_detached(value: T) -> Inner<T> {
-        Inner::new(
-            value,
-            Arc::new(PoolHead {
-                is_detached: AtomicBool::new(true),
-                head: epoch::Atomic::null(),
-            }),
-        )
+        Inner::new(value, next_object_id(), Arc::new(PoolHead::new_detached()))
+    }
+}
+
+impl<T> PoolHead<T> {
+    fn new(params: Params<T>) -> PoolHead<T> {
+        PoolHead {
+            id: NEXT_POOL_ID.fetch_add(1, Ordering::Relaxed),
+            is_detached: AtomicBool::new(false),
+            heads: (0 .. params.size_classes).map(|_| epoch::Atomic::null()).collect(),
+            idle: AtomicUsize::new(0),
+            idle_weight: AtomicUsize::new(0),
+            slots: wait::Slots::new(params.max_outstanding),
+            handles: AtomicUsize::new(0),
+            stats: if params.stats { Some(stats::Counters::default()) } else { None },
+            holds: if params.hold_histogram { Some(hold::HoldCounters::default()) } else { None },
+            #[cfg(feature = "leak-detection")]
+            leaks: leak::Registry::default(),
+            created_at: params.clock.now(),
+            eviction_due: AtomicU64::new(0),
+            params,
+        }
+    }
+
+    fn new_detached() -> PoolHead<T> {
+        let pool_head = PoolHead::new(Params::default());
+        pool_head.is_detached.store(true, Ordering::SeqCst);
+        // account for the only value this head is created for
+        pool_head.slots.try_acquire();
+        pool_head
+    }
+
+    fn attach_handle(&self) {
+        self.handles.fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn detach_handle(&self) {
+        if self.handles.fetch_sub(1, Ordering::AcqRel) == 1 {
+            #[cfg(feature = "leak-detection")]
+            self.leaks.pool_detached(self.id, self.params.clock.now(), &self.params.leak_detection);
+        }
+    }
+
+    fn lent(&self, object_id: u64, hit: bool) {
+        trace_event!("lend", pool_id = self.id, object_id = object_id, hit = hit);
+        if let Some(member) = &self.params.memory_budget {
+            member.touch();
+        }
+        #[cfg(feature = "leak-detection")]
+        self.leaks.register(object_id, self.params.clock.now(), &self.params.leak_detection);
+        if let Some(stats) = &self.stats {
+            stats.lent(hit, self.slots.outstanding());
+        }
+    }
+
+    fn lent_at(&self) -> Option<Instant> {
+        // long holds can only be reported to a callback or through `tracing`
+        let reports_long_holds = self.params.hold_threshold.is_some()
+            && (self.params.on_long_hold.is_some() || cfg!(feature = "tracing"));
+        if reports_long_holds || self.params.hold_histogram {
+            Some(self.params.clock.now())
+        } else {
+            None
+        }
+    }
+
+    fn held(&self, value: &T, _object_id: u64, lent_at: Instant) {
+        let held = self.params.clock.now().saturating_duration_since(lent_at);
+        if let Some(holds) = &self.holds {
+            holds.record(held);
+        }
+        match (self.params.hold_threshold, &self.params.on_long_hold) {
+            (Some(hold_threshold), Some(on_long_hold)) if held > hold_threshold =>
+                on_long_hold(value, held),
+            #[cfg(feature = "tracing")]
+            (Some(hold_threshold), None) if held > hold_threshold =>
+                tracing::warn!(pool_id = self.id, object_id = _object_id, ?held, ?hold_threshold, "value held longer than threshold"),
+            _ =>
+                (),
+        }
+    }
+
+    fn stats(&self) -> Option<stats::PoolStats> {
+        self.stats.as_ref()
+            .map(|stats| stats.snapshot(
+                self.idle.load(Ordering::Relaxed),
+                self.idle_weight.load(Ordering::Relaxed),
+                self.slots.outstanding(),
+            ))
+    }
+
+    fn pop_idle(&self) -> Option<(T, u64)> {
+        self.pop_idle_from(0)
+    }
+
+    /// Pops an idle value from the smallest nonempty size class starting with `min_class`.
+    fn pop_idle_from(&self, min_class: usize) -> Option<(T, u64)> {
+        let expired_before = self.expired_before();
+        let guard = epoch::pin();
+        self.heads.iter()
+            .skip(min_class)
+            .find_map(|head| self.pop_idle_head(head, expired_before, &guard))
+    }
+
+    /// Pops an idle value from the largest nonempty size class, so the most memory is released
+    /// first when idle values are dropped.
+    fn pop_idle_largest(&self) -> Option<(T, u64)> {
+        let expired_before = self.expired_before();
+        let guard = epoch::pin();
+        self.heads.iter()
+            .rev()
+            .find_map(|head| self.pop_idle_head(head, expired_before, &guard))
+    }
+
+    fn pop_idle_head(&self, pool_head: &epoch::Atomic<Entry<T>>, expired_before: Option<Instant>, guard: &epoch::Guard) -> Option<(T, u64)> {
+        loop {
+            let head = pool_head.load(Ordering::Acquire, guard);
+            match unsafe { head.as_ref() } {
+                Some(entry) => {
+                    let next = entry.next.load(Ordering::Relaxed, guard);
+                    if pool_head.compare_exchange(head, next, Ordering::Relaxed, Ordering::Relaxed, guard).is_ok() {
+                        self.idle.fetch_sub(1, Ordering::Relaxed);
+                        self.idle_weight_released(entry.weight);
+                        let value = unsafe {
+                            guard.defer_destroy(head);
+                            ManuallyDrop::into_inner(
+                                ptr::read(&entry.value),
+                            )
+                        };
+                        if is_expired(entry.returned_at, expired_before) {
+                            // drop expired value and try the next one
+                            continue;
+                        }
+                        return Some((value, entry.object_id));
+                    }
+                },
+                None =>
+                    return None,
+            }
+        }
+    }
+
+    fn push_idle(&self, mut value: T, object_id: u64) {
+        if let Some(stats) = &self.stats {
+            stats.returned();
+        }
+        if self.is_detached.load(Ordering::SeqCst) {
+            self.returned_detached(object_id);
+            return;
+        }
+        if let Some(return_filter) = &self.params.return_filter {
+            if !return_filter(&value) {
+                trace_event!("return rejected by filter", pool_id = self.id, object_id = object_id);
+                self.returned_dropped();
+                return;
+            }
+        }
+        if let Some(member) = &self.params.memory_budget {
+            member.touch();
+        }
+        self.maybe_evict_expired();
+        // reserve a place in idle list first so it never grows beyond `max_idle`
+        if !self.reserve_idle() {
+            trace_event!("return dropped: idle list is full", pool_id = self.id, object_id = object_id);
+            self.returned_dropped();
+            return;
+        }
+        if let Some(recycle) = self.params.recycle {
+            recycle(&mut value);
+        }
+        if self.push_reserved(value, object_id, self.returned_at()) {
+            trace_event!("return reenqueued", pool_id = self.id, object_id = object_id);
+        } else if self.is_detached.load(Ordering::SeqCst) {
+            self.returned_detached(object_id);
+        } else {
+            trace_event!("return dropped: memory budget is exhausted", pool_id = self.id, object_id = object_id);
+            self.returned_dropped();
+        }
+    }
+
+    fn returned_detached(&self, object_id: u64) {
+        trace_event!("return dropped: pool is detached", pool_id = self.id, object_id = object_id);
+        self.returned_dropped();
+    }
+
+    fn returned_dropped(&self) {
+        if let Some(stats) = &self.stats {
+            stats.returned_dropped();
+        }
+    }
+
+    fn fill_idle<F>(&self, count: usize, mut make_value: F) -> usize where F: FnMut() -> T {
+        let mut created = 0;
+        while created < count && self.reserve_idle() {
+            if !self.push_reserved(make_value(), next_object_id(), self.returned_at()) {
+                break;
+            }
+            created += 1;
+        }
+        created
+    }
+
+    fn fill_idle_up_to<F>(&self, idle_target: usize, mut make_value: F) -> usize where F: FnMut() -> T {
+        let idle_target = match self.params.max_idle {
+            Some(max_idle) if max_idle < idle_target =>
+                max_idle,
+            _ =>
+                idle_target,
+        };
+        let mut created = 0;
+        while self.reserve_idle_below(idle_target) {
+            if !self.push_reserved(make_value(), next_object_id(), self.returned_at()) {
+                break;
+            }
+            created += 1;
+        }
+        created
+    }
+
+    fn push_reserved(&self, value: T, object_id: u64, returned_at: Option<Instant>) -> bool {
+        let weight = self.params.weigh.map_or(0, |weigh| weigh(&value));
+        let class = self.params.classify
+            .map_or(0, |classify| classify(&value).min(self.heads.len() - 1));
+        let pool_head = &self.heads[class];
+        if let Some(member) = &self.params.memory_budget {
+            if !member.charge(weight) {
+                self.idle.fetch_sub(1, Ordering::Relaxed);
+                return false;
+            }
+        }
+        self.idle_weight.fetch_add(weight, Ordering::Relaxed);
+        let mut owned_entry = epoch::Owned::new(Entry {
+            value: ManuallyDrop::new(value),
+            returned_at,
+            weight,
+            object_id,
+            next: epoch::Atomic::null(),
+        });
+        let guard = epoch::pin();
+        loop {
+            if self.is_detached.load(Ordering::SeqCst) {
+                // pool is detached, terminate reenqueue process and drop entry
+                self.idle.fetch_sub(1, Ordering::Relaxed);
+                self.idle_weight_released(weight);
+                let entry_value = &owned_entry.value;
+                let _value = ManuallyDrop::into_inner(
+                    unsafe { ptr::read(entry_value) },
+                );
+                return false;
+            }
+
+            let head = pool_head.load(Ordering::Relaxed, &guard);
+            owned_entry.next.store(head, Ordering::Relaxed);
+
+            match pool_head.compare_exchange(head, owned_entry, Ordering::Release, Ordering::Relaxed, &guard) {
+                Ok(..) =>
+                    return true,
+                Err(error) =>
+                    owned_entry = error.new,
+            }
+        }
+    }
+
+    /// Detaches the whole idle lists at once and puts back only the entries accepted by
+    /// `keep`, preserving their order. Returns the number of entries rejected by `keep`.
+    /// Concurrent lends see empty idle lists meanwhile, so they may make new values, and kept
+    /// entries which no longer fit into the idle list afterwards are dropped.
+    fn retain_idle<F>(&self, mut keep: F) -> usize where F: FnMut(&T, Option<Instant>) -> bool {
+        let guard = epoch::pin();
+        let mut kept = Vec::new();
+        let mut dropped = 0;
+        // values are visited starting from the largest size class
+        for pool_head in self.heads.iter().rev() {
+            let mut current = pool_head.swap(epoch::Shared::null(), Ordering::AcqRel, &guard);
+            while let Some(entry) = unsafe { current.as_ref() } {
+                self.idle.fetch_sub(1, Ordering::Relaxed);
+                self.idle_weight_released(entry.weight);
+                let value = unsafe {
+                    guard.defer_destroy(current);
+                    ManuallyDrop::into_inner(
+                        ptr::read(&entry.value),
+                    )
+                };
+                if keep(&value, entry.returned_at) {
+                    kept.push((value, entry.object_id, entry.returned_at));
+                } else {
+                    dropped += 1;
+                }
+                current = entry.next.load(Ordering::Relaxed, &guard);
+            }
+        }
+
+        // entries are pushed back as new allocations, so concurrent `pop_idle` cannot be fooled by ABA
+        while let Some((value, object_id, returned_at)) = kept.pop() {
+            if self.reserve_idle() {
+                self.push_reserved(value, object_id, returned_at);
+            }
+        }
+        dropped
+    }
+
+    fn idle_weight_released(&self, weight: usize) {
+        self.idle_weight.fetch_sub(weight, Ordering::Relaxed);
+        if let Some(member) = &self.params.memory_budget {
+            member.credit(weight);
+        }
+    }
+
+    fn evict_expired(&self) -> usize {
+        match self.expired_before() {
+            Some(expired_before) =>
+                self.retain_idle(|_value, returned_at| !is_expired(returned_at, Some(expired_before))),
+            None =>
+                0,
+        }
+    }
+
+    fn maybe_evict_expired(&self) {
+        let Some(idle_ttl) = self.params.idle_ttl else {
+            return;
+        };
+        let now = self.params.clock.now();
+        let elapsed = now.saturating_duration_since(self.created_at).as_nanos() as u64;
+        let eviction_due = self.eviction_due.load(Ordering::Relaxed);
+        if elapsed < eviction_due {
+            return;
+        }
+        let next_eviction_due = elapsed + idle_ttl.as_nanos() as u64;
+        // only one thread performs eviction until the next one is due
+        if self.eviction_due.compare_exchange(eviction_due, next_eviction_due, Ordering::Relaxed, Ordering::Relaxed).is_ok() {
+            self.evict_expired();
+        }
+    }
+
+    fn trim_idle_to(&self, idle_target: usize) -> usize {
+        let mut dropped = 0;
+        while self.idle.load(Ordering::Relaxed) > idle_target {
+            match self.pop_idle_largest() {
+                Some(..) =>
+                    dropped += 1,
+                None =>
+                    break,
+            }
+        }
+        dropped
+    }
+
+    fn trim_weight_to(&self, weight_target: usize) -> usize {
+        let mut dropped = 0;
+        while self.idle_weight.load(Ordering::Relaxed) > weight_target {
+            match self.pop_idle_largest() {
+                Some(..) =>
+                    dropped += 1,
+                None =>
+                    break,
+            }
+        }
+        dropped
+    }
+
+    fn maintain<F>(&self, make_value: Option<F>) -> usize where F: FnMut() -> T {
+        self.evict_expired();
+        if let Some(max_idle) = self.params.max_idle {
+            self.trim_idle_to(max_idle);
+        }
+        match (self.params.min_idle, make_value) {
+            (Some(min_idle), Some(make_value)) =>
+                self.fill_idle_up_to(min_idle, make_value),
+            _ =>
+                0,
+        }
+    }
+
+    fn returned_at(&self) -> Option<Instant> {
+        self.params.idle_ttl.map(|_| self.params.clock.now())
+    }
+
+    fn expired_before(&self) -> Option<Instant> {
+        self.params.idle_ttl
+            .and_then(|idle_ttl| self.params.clock.now().checked_sub(idle_ttl))
+    }
+
+    fn reserve_idle(&self) -> bool {
+        match self.params.max_idle {
+            None => {
+                let idle = self.idle.fetch_add(1, Ordering::Relaxed);
+                self.idle_reserved(idle + 1);
+                true
+            },
+            Some(max_idle) =>
+                self.reserve_idle_below(max_idle),
+        }
+    }
+
+    fn reserve_idle_below(&self, limit: usize) -> bool {
+        match self.idle.fetch_update(Ordering::Relaxed, Ordering::Relaxed, |idle| if idle < limit { Some(idle + 1) } else { None }) {
+            Ok(idle) => {
+                self.idle_reserved(idle + 1);
+                true
+            },
+            Err(..) =>
+                false,
+        }
+    }
+
+    fn idle_reserved(&self, idle: usize) {
+        if let Some(stats) = &self.stats {
+            stats.idle_reserved(idle);
+        }
+    }
+}
+
+fn is_expired(returned_at: Option<Instant>, expired_before: Option<Instant>) -> bool {
+    match (returned_at, expired_before) {
+        (Some(returned_at), Some(expired_before)) =>
+            returned_at < expired_before,
+        _ =>
+            false,
     }
 }
 
@@ -201,31 +799,14 @@ impl<T> Hash for Unique<T> where T: Hash {
 impl<T> Drop for Inner<T> {
     fn drop(&mut self) {
         if let Some(value) = self.value.take() {
-            let mut owned_entry = epoch::Owned::new(Entry {
-                value: ManuallyDrop::new(value),
-                next: epoch::Atomic::null(),
-            });
-            let guard = epoch::pin();
-            loop {
-                if self.pool_head.is_detached.load(Ordering::SeqCst) {
-                    // pool is detached, terminate reenqueue process and drop entry
-                    let entry_value = &owned_entry.value;
-                    let _value = ManuallyDrop::into_inner(
-                        unsafe { ptr::read(entry_value) },
-                    );
-                    break;
-                }
-
-                let head = self.pool_head.head.load(Ordering::Relaxed, &guard);
-                owned_entry.next.store(head, Ordering::Relaxed);
-
-                match self.pool_head.head.compare_exchange(head, owned_entry, Ordering::Release, Ordering::Relaxed, &guard) {
-                    Ok(..) =>
-                        break,
-                    Err(error) =>
-                        owned_entry = error.new,
-                }
+            // released even if one of user callbacks panics
+            let _slot_guard = wait::SlotGuard::new(&self.pool_head.slots);
+            #[cfg(feature = "leak-detection")]
+            self.pool_head.leaks.unregister(self.object_id);
+            if let Some(lent_at) = self.lent_at {
+                self.pool_head.held(&value, self.object_id, lent_at);
             }
+            self.pool_head.push_idle(value, self.object_id);
         }
     }
 }
@@ -236,25 +817,31 @@ impl<T> Drop for PoolHead<T> {
         self.is_detached.store(true, Ordering::SeqCst);
 
         // drop entries
+        let mut destroyed = 0;
         let guard = epoch::pin();
-        loop {
-            let head = self.head.load(Ordering::Acquire, &guard);
-            match unsafe { head.as_ref() } {
-                Some(entry) => {
-                    let next = entry.next.load(Ordering::Relaxed, &guard);
-                    if self.head.compare_exchange(head, next, Ordering::Relaxed, Ordering::Relaxed, &guard).is_ok() {
-                        unsafe {
-                            guard.defer_destroy(head);
-                            let _value = ManuallyDrop::into_inner(
-                                ptr::read(&entry.value),
-                            );
+        for pool_head in self.heads.iter() {
+            loop {
+                let head = pool_head.load(Ordering::Acquire, &guard);
+                match unsafe { head.as_ref() } {
+                    Some(entry) => {
+                        let next = entry.next.load(Ordering::Relaxed, &guard);
+                        if pool_head.compare_exchange(head, next, Ordering::Relaxed, Ordering::Relaxed, &guard).is_ok() {
+                            self.idle_weight_released(entry.weight);
+                            unsafe {
+                                guard.defer_destroy(head);
+                                let _value = ManuallyDrop::into_inner(
+                                    ptr::read(&entry.value),
+                                );
+                            }
+                            destroyed += 1;
                         }
-                    }
-                },
-                None =>
-                    break,
+                    },
+                    None =>
+                        break,
+                }
             }
         }
+        trace_event!("pool destroyed", pool_id = self.id, destroyed = destroyed);
     }
 }
 
@@ -274,6 +861,7 @@ mod tests {
     use super::{
         pool::Pool,
         bytes::BytesPool,
+        Unique,
     };
 
     #[test]
@@ -341,4 +929,147 @@ mod tests {
             let _bytes = bytes.freeze();
         });
     }
+
+    #[test]
+    fn shared_try_into_unique() {
+        let pool = Pool::new();
+        let shared = pool.lend(|| vec![1, 2, 3]).freeze();
+        let shared_cloned = shared.clone();
+        let shared = shared.try_into_unique().unwrap_err();
+        drop(shared_cloned);
+        let weak = shared.downgrade();
+
+        let mut unique = shared.try_into_unique().unwrap();
+        assert!(weak.upgrade().is_none());
+        unique.push(4);
+        drop(unique);
+        assert_eq!(*pool.lend(Vec::new), [1, 2, 3, 4]);
+    }
+
+    #[test]
+    fn shared_make_mut() {
+        let pool = Pool::builder().max_outstanding(2).stats().build();
+        let mut shared = pool.lend(|| vec![1, 2, 3]).freeze();
+        shared.make_mut().push(4);
+        assert_eq!(*shared, [1, 2, 3, 4]);
+
+        let shared_cloned = shared.clone();
+        let value = pool.lend(Vec::new);
+        pool.prefill(1, || Vec::with_capacity(16));
+        // copy is made in idle value even though `max_outstanding` is reached
+        shared.make_mut().push(5);
+        assert_eq!(*shared, [1, 2, 3, 4, 5]);
+        assert_eq!(*shared_cloned, [1, 2, 3, 4]);
+        assert!(shared.capacity() >= 16);
+        assert_eq!(pool.stats().unwrap().hits, 1);
+        assert_eq!(pool.stats().unwrap().outstanding, 3);
+
+        drop(value);
+        drop(shared_cloned);
+        drop(shared);
+        assert_eq!(pool.stats().unwrap().outstanding, 0);
+        assert_eq!(pool.stats().unwrap().idle, 3);
+
+        let mut detached = Unique::new_detached(1).freeze();
+        let detached_cloned = detached.clone();
+        *detached.make_mut() += 1;
+        assert_eq!((*detached, *detached_cloned), (2, 1));
+    }
+
+    #[cfg(feature = "tracing")]
+    #[test]
+    fn tracing_events() {
+        use std::sync::Mutex;
+
+        #[derive(Default)]
+        struct Collector {
+            events: Mutex<Vec<(String, u64)>>,
+        }
+
+        #[derive(Default)]
+        struct Visitor {
+            message: String,
+            object_id: u64,
+        }
+
+        impl tracing::field::Visit for Visitor {
+            fn record_u64(&mut self, field: &tracing::field::Field, value: u64) {
+                if field.name() == "object_id" {
+                    self.object_id = value;
+                }
+            }
+
+            fn record_debug(&mut self, field: &tracing::field::Field, value: &dyn std::fmt::Debug) {
+                if field.name() == "message" {
+                    self.message = format!("{value:?}");
+                }
+            }
+        }
+
+        impl tracing::Subscriber for Collector {
+            fn enabled(&self, _metadata: &tracing::Metadata<'_>) -> bool {
+                true
+            }
+
+            fn new_span(&self, _span: &tracing::span::Attributes<'_>) -> tracing::span::Id {
+                tracing::span::Id::from_u64(1)
+            }
+
+            fn record(&self, _span: &tracing::span::Id, _values: &tracing::span::Record<'_>) { }
+
+            fn record_follows_from(&self, _span: &tracing::span::Id, _follows: &tracing::span::Id) { }
+
+            fn event(&self, event: &tracing::Event<'_>) {
+                let mut visitor = Visitor::default();
+                event.record(&mut visitor);
+                self.events.lock().unwrap().push((visitor.message, visitor.object_id));
+            }
+
+            fn enter(&self, _span: &tracing::span::Id) { }
+
+            fn exit(&self, _span: &tracing::span::Id) { }
+        }
+
+        let collector = Arc::new(Collector::default());
+        tracing::subscriber::with_default(collector.clone(), || {
+            let pool = Pool::new();
+            drop(pool.lend(|| 0_u8));
+            drop(pool.lend(|| 1_u8).freeze());
+        });
+
+        let events = collector.events.lock().unwrap();
+        let messages: Vec<_> = events.iter()
+            .map(|(message, _object_id)| message.as_str())
+            .collect();
+        assert_eq!(messages, ["lend", "return reenqueued", "lend", "freeze", "return reenqueued", "pool destroyed"]);
+        // the same object is followed across both lends
+        assert!(events[.. 5].iter().all(|(_message, object_id)| *object_id == events[0].1));
+        drop(events);
+
+        let collector = Arc::new(Collector::default());
+        tracing::subscriber::with_default(collector.clone(), || {
+            let pool = Pool::builder()
+                .hold_threshold(std::time::Duration::ZERO)
+                .build();
+            let value = pool.lend(|| 0_u8);
+            std::thread::sleep(std::time::Duration::from_millis(1));
+            drop(value);
+        });
+        let events = collector.events.lock().unwrap();
+        assert!(events.iter().any(|(message, _object_id)| message == "value held longer than threshold"));
+        drop(events);
+
+        #[cfg(feature = "leak-detection")]
+        {
+            let collector = Arc::new(Collector::default());
+            tracing::subscriber::with_default(collector.clone(), || {
+                let pool = Pool::builder().on_leak(crate::leak::LeakAction::Log).build();
+                let value = pool.lend(|| 0_u8);
+                drop(pool);
+                drop(value);
+            });
+            let events = collector.events.lock().unwrap();
+            assert!(events.iter().any(|(message, _object_id)| message.contains("dropped while 1 lent values are still out")));
+        }
+    }
 }
@@ -0,0 +1,79 @@
+use std::{
+    io,
+    thread,
+    sync::{
+        Arc,
+        Weak,
+        atomic::{
+            Ordering,
+            AtomicBool,
+        },
+    },
+    time::{
+        Duration,
+        Instant,
+    },
+};
+
+use crate::{
+    PoolHead,
+};
+
+/// Background thread applying idle ttl, max idle and min idle policies of a pool on a fixed
+/// interval. It terminates by itself as soon as the pool and all values lent from it are gone.
+#[derive(Debug)]
+pub struct PoolMaintainer {
+    handle: thread::JoinHandle<()>,
+    shutdown: Arc<AtomicBool>,
+}
+
+impl PoolMaintainer {
+    pub(crate) fn spawn<T, F>(pool_head: Weak<PoolHead<T>>, interval: Duration, mut make_value: Option<F>) -> io::Result<PoolMaintainer>
+    where T: Send + Sync + 'static,
+          F: FnMut() -> T + Send + 'static,
+    {
+        let shutdown = Arc::new(AtomicBool::new(false));
+        let handle = thread::Builder::new()
+            .name("alloc-pool maintainer".to_string())
+            .spawn({
+                let shutdown = shutdown.clone();
+                move || {
+                    loop {
+                        let deadline = Instant::now() + interval;
+                        loop {
+                            if shutdown.load(Ordering::SeqCst) {
+                                return;
+                            }
+                            let now = Instant::now();
+                            if now >= deadline {
+                                break;
+                            }
+                            thread::park_timeout(deadline - now);
+                        }
+
+                        let Some(pool_head) = pool_head.upgrade() else {
+                            return;
+                        };
+                        pool_head.maintain(make_value.as_mut());
+                    }
+                }
+            })?;
+        Ok(PoolMaintainer { handle, shutdown, })
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.handle.is_finished()
+    }
+
+    /// Waits until the maintainer terminates, that is when the pool is gone.
+    pub fn join(self) -> thread::Result<()> {
+        self.handle.join()
+    }
+
+    /// Terminates the maintainer without waiting for the pool to be dropped.
+    pub fn stop(self) -> thread::Result<()> {
+        self.shutdown.store(true, Ordering::SeqCst);
+        self.handle.thread().unpark();
+        self.handle.join()
+    }
+}
@@ -0,0 +1,140 @@
+use metrics::{
+    Label,
+    gauge,
+    counter,
+};
+
+use crate::{
+    stats::PoolStats,
+};
+
+pub const LEND_HIT_RATIO: &str = "alloc_pool_lend_hit_ratio";
+pub const IDLE: &str = "alloc_pool_idle";
+pub const IDLE_BYTES: &str = "alloc_pool_idle_bytes";
+pub const OUTSTANDING: &str = "alloc_pool_outstanding";
+pub const RETURNS_DROPPED: &str = "alloc_pool_returns_dropped";
+
+/// Publishes pool statistics through `metrics` facade. Every metric is labeled with
+/// `pool = <pool name>` and any extra labels given.
+#[derive(Clone, Debug)]
+pub struct PoolMetrics {
+    labels: Vec<Label>,
+}
+
+impl PoolMetrics {
+    pub fn new<N>(pool_name: N) -> PoolMetrics where N: Into<String> {
+        PoolMetrics {
+            labels: vec![Label::new("pool", pool_name.into())],
+        }
+    }
+
+    pub fn label<K, V>(mut self, key: K, value: V) -> Self where K: Into<String>, V: Into<String> {
+        self.labels.push(Label::new(key.into(), value.into()));
+        self
+    }
+
+    pub(crate) fn publish(&self, stats: &PoolStats, with_idle_bytes: bool) {
+        let lends = stats.hits + stats.misses;
+        let lend_hit_ratio = if lends == 0 {
+            0.0
+        } else {
+            stats.hits as f64 / lends as f64
+        };
+        gauge!(LEND_HIT_RATIO, self.labels.clone()).set(lend_hit_ratio);
+        gauge!(IDLE, self.labels.clone()).set(stats.idle as f64);
+        gauge!(OUTSTANDING, self.labels.clone()).set(stats.outstanding as f64);
+        counter!(RETURNS_DROPPED, self.labels.clone()).absolute(stats.returns_dropped);
+        if with_idle_bytes {
+            gauge!(IDLE_BYTES, self.labels.clone()).set(stats.idle_weight as f64);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        collections::{
+            HashMap,
+        },
+    };
+
+    use metrics_util::{
+        debugging::{
+            DebugValue,
+            DebuggingRecorder,
+        },
+    };
+
+    use super::{
+        PoolMetrics,
+        IDLE,
+        IDLE_BYTES,
+        OUTSTANDING,
+        LEND_HIT_RATIO,
+        RETURNS_DROPPED,
+    };
+
+    use crate::{
+        pool::Pool,
+        bytes::BytesPool,
+    };
+
+    fn collect<F>(publish: F) -> HashMap<String, (Vec<(String, String)>, DebugValue)> where F: FnOnce() {
+        let recorder = DebuggingRecorder::new();
+        let snapshotter = recorder.snapshotter();
+        metrics::with_local_recorder(&recorder, publish);
+        snapshotter.snapshot()
+            .into_vec()
+            .into_iter()
+            .map(|(composite_key, _unit, _description, value)| {
+                let key = composite_key.key();
+                let labels = key.labels()
+                    .map(|label| (label.key().to_string(), label.value().to_string()))
+                    .collect();
+                (key.name().to_string(), (labels, value))
+            })
+            .collect()
+    }
+
+    #[test]
+    fn publish_pool() {
+        let pool = Pool::builder().stats().max_idle(1).build();
+        let value_a = pool.lend(|| 0);
+        drop(value_a);
+        let value_a = pool.lend(|| 1);
+        let value_b = pool.lend(|| 2);
+        drop(value_a);
+        // idle list is full already
+        drop(value_b);
+
+        let metrics = PoolMetrics::new("samples").label("shard", "7");
+        let published = collect(|| pool.publish_metrics(&metrics));
+
+        let labels = vec![
+            ("pool".to_string(), "samples".to_string()),
+            ("shard".to_string(), "7".to_string()),
+        ];
+        assert_eq!(published.len(), 4);
+        assert_eq!(published[LEND_HIT_RATIO], (labels.clone(), DebugValue::Gauge((1.0 / 3.0).into())));
+        assert_eq!(published[IDLE], (labels.clone(), DebugValue::Gauge(1.0.into())));
+        assert_eq!(published[OUTSTANDING], (labels.clone(), DebugValue::Gauge(0.0.into())));
+        assert_eq!(published[RETURNS_DROPPED], (labels, DebugValue::Counter(1)));
+
+        assert!(collect(|| Pool::<u8>::new().publish_metrics(&metrics)).is_empty());
+    }
+
+    #[test]
+    fn publish_bytes_pool() {
+        let pool = BytesPool::builder().stats().build();
+        let bytes_a = pool.try_lend(100).unwrap();
+        let bytes_b = pool.try_lend(28).unwrap();
+        let idle_bytes = bytes_a.capacity() + bytes_b.capacity();
+        drop(bytes_a);
+        drop(bytes_b);
+
+        let published = collect(|| pool.publish_metrics(&PoolMetrics::new("frames")));
+        assert_eq!(published.len(), 5);
+        assert_eq!(published[IDLE].1, DebugValue::Gauge(2.0.into()));
+        assert_eq!(published[IDLE_BYTES].1, DebugValue::Gauge((idle_bytes as f64).into()));
+    }
+}
@@ -1,72 +1,1395 @@
 use std::{
-    ptr,
-    mem::{
-        ManuallyDrop,
+    io,
+    fmt,
+    error,
+    future::{
+        Future,
+    },
+    convert::{
+        Infallible,
     },
     sync::{
         Arc,
         atomic::{
             Ordering,
-            AtomicBool,
         },
     },
+    time::{
+        Duration,
+    },
 };
 
-use crossbeam_epoch as epoch;
-
 use crate::{
+    wait,
+    clock,
     Inner,
+    Params,
     Unique,
     PoolHead,
+    next_object_id,
+    registry,
+    budget,
+    stats::PoolStats,
+    pressure::PurgePolicy,
+    hold::HoldHistogram,
+    recycle::Recycle,
+    maintainer::PoolMaintainer,
+};
+
+#[cfg(feature = "metrics")]
+use crate::metrics::PoolMetrics;
+
+#[cfg(feature = "leak-detection")]
+use crate::leak::{
+    LeakAction,
+    OutstandingHandle,
 };
 
-#[derive(Debug)]
 pub struct Pool<T> {
     inner: Arc<PoolHead<T>>,
 }
 
+impl<T> fmt::Debug for Pool<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Pool")
+            .field("id", &self.id())
+            .field("name", &self.inner.params.name)
+            .field("idle", &self.inner.idle.load(Ordering::Relaxed))
+            .field("outstanding", &self.inner.slots.outstanding())
+            .finish()
+    }
+}
+
 impl<T> Clone for Pool<T> {
     fn clone(&self) -> Pool<T> {
-        Pool { inner: self.inner.clone(), }
+        Pool::attach(self.inner.clone())
     }
 }
 
-impl<T> Default for Pool<T> {
+impl<T> Drop for Pool<T> {
+    fn drop(&mut self) {
+        self.inner.detach_handle();
+    }
+}
+
+impl<T> Default for Pool<T> where T: Send + Sync + 'static {
     fn default() -> Self {
         Self::new()
     }
 }
 
 impl<T> Pool<T> {
-    pub fn new() -> Pool<T> {
-        Pool {
-            inner: Arc::new(PoolHead {
-                is_detached: AtomicBool::new(false),
-                head: epoch::Atomic::null(),
-            }),
-        }
+    pub fn new() -> Pool<T> where T: Send + Sync + 'static {
+        PoolBuilder::new().build()
+    }
+
+    pub fn builder() -> PoolBuilder<T> {
+        PoolBuilder::new()
+    }
+
+    fn attach(inner: Arc<PoolHead<T>>) -> Pool<T> {
+        inner.attach_handle();
+        Pool { inner, }
+    }
+
+    /// Creates a pool listed by `registry::snapshot` under `name`.
+    pub fn named<S>(name: S) -> Pool<T> where S: Into<String>, T: Send + Sync + 'static {
+        PoolBuilder::new().build_named(name)
+    }
+
+    /// Creates a pool which keeps at most `max_idle` values in idle list: values returned
+    /// to a full pool are dropped instead.
+    pub fn with_max_idle(max_idle: usize) -> Pool<T> where T: Send + Sync + 'static {
+        PoolBuilder::new().max_idle(max_idle).build()
+    }
+
+    /// Creates a pool which resets values with `Recycle::recycle` when they are returned back.
+    pub fn new_recycling() -> Pool<T> where T: Recycle + Send + Sync + 'static {
+        PoolBuilder::new().recycling().build()
+    }
+
+    /// Creates a pool which drops returned values rejected by `return_filter` instead of
+    /// putting them back to idle list.
+    pub fn with_return_filter<F>(return_filter: F) -> Pool<T> where F: Fn(&T) -> bool + Send + Sync + 'static, T: Send + Sync + 'static {
+        PoolBuilder::new().return_filter(return_filter).build()
     }
 
+    /// Creates a pool which stores `factory` for making new values, so `lend` needs no closure.
+    pub fn with_factory<F>(factory: F) -> FactoryPool<T, F> where F: Fn() -> T, T: Send + Sync + 'static {
+        PoolBuilder::new().build_with_factory(factory)
+    }
+
+    /// Lends a value from idle list or makes a new one. For a pool with `max_outstanding` limit
+    /// this blocks until some value is returned back.
     pub fn lend<F>(&self, make_value: F) -> Unique<T> where F: FnOnce() -> T {
-        let guard = epoch::pin();
+        self.inner.slots.acquire(None);
+        self.lend_acquired(make_value)
+    }
+
+    /// Same as `lend`, but gives up waiting for `max_outstanding` limit after `timeout`.
+    pub fn lend_blocking<F>(&self, timeout: Duration, make_value: F) -> Result<Unique<T>, LendTimeout> where F: FnOnce() -> T {
+        if !self.inner.slots.acquire(Some(timeout)) {
+            return Err(LendTimeout);
+        }
+        Ok(self.lend_acquired(make_value))
+    }
+
+    /// Same as `lend`, but waits for `max_outstanding` limit by parking the task instead of
+    /// the thread. Waiting tasks are served first come first served.
+    pub async fn lend_async<F>(&self, make_value: F) -> Unique<T> where F: FnOnce() -> T {
+        self.inner.slots.acquire_async().await;
+        self.lend_acquired(make_value)
+    }
+
+    /// Lends a value from idle list which passes `is_healthy` check or makes a new one. Idle
+    /// values failing the check are dropped, their count is returned along with the value.
+    pub fn lend_checked<F, H>(&self, make_value: F, is_healthy: H) -> (Unique<T>, usize) where F: FnOnce() -> T, H: FnMut(&T) -> bool {
+        self.inner.slots.acquire(None);
+        self.lend_acquired_checked(0, make_value, is_healthy)
+    }
+
+    /// Lends a value from idle list or makes a new one with fallible `make_value`. Its error is
+    /// returned as is, idle list is not touched in this case.
+    pub fn try_lend<E, F>(&self, make_value: F) -> Result<Unique<T>, E> where F: FnOnce() -> Result<T, E> {
+        self.try_lend_from_class(0, make_value)
+    }
+
+    /// Lends a value from idle list or awaits a new one from async `make_value` on a pool miss.
+    /// Cancelling the future leaves the pool intact.
+    pub async fn lend_with_async<F, R>(&self, make_value: F) -> Unique<T> where F: FnOnce() -> R, R: Future<Output = T> {
+        let result = self.try_lend_with_async(|| async { Ok::<_, Infallible>(make_value().await) }).await;
+        match result {
+            Ok(unique) =>
+                unique,
+            Err(never) =>
+                match never { },
+        }
+    }
+
+    /// Same as `lend_with_async`, but with fallible `make_value`.
+    pub async fn try_lend_with_async<E, F, R>(&self, make_value: F) -> Result<Unique<T>, E> where F: FnOnce() -> R, R: Future<Output = Result<T, E>> {
+        self.inner.slots.acquire_async().await;
+        let slot_guard = wait::SlotGuard::new(&self.inner.slots);
+        let (value, object_id, hit) = match self.inner.pop_idle() {
+            Some((value, object_id)) =>
+                (value, object_id, true),
+            None =>
+                (make_value().await?, next_object_id(), false),
+        };
+        slot_guard.disarm();
+        self.inner.lent(object_id, hit);
+        Ok(Unique { inner: Inner::new(value, object_id, self.inner.clone()), })
+    }
+
+    /// Makes and puts up to `count` fresh values to idle list at once, stopping early when
+    /// `max_idle` is reached. Returns the number of values created.
+    pub fn prefill<F>(&self, count: usize, make_value: F) -> usize where F: FnMut() -> T {
+        self.inner.fill_idle(count, make_value)
+    }
+
+    /// Applies idle ttl and max idle policies and refills idle list back up to `min_idle`, if
+    /// configured. Returns the number of values created.
+    pub fn maintain<F>(&self, make_value: F) -> usize where F: FnMut() -> T {
+        self.inner.maintain(Some(make_value))
+    }
+
+    /// Drops idle values which have been sitting in the pool longer than `idle_ttl`.
+    /// Returns the number of values dropped.
+    pub fn evict_expired(&self) -> usize {
+        self.inner.evict_expired()
+    }
+
+    pub(crate) fn id(&self) -> u64 {
+        self.inner.id
+    }
+
+    /// Returns a snapshot of pool counters if the pool has been built with `PoolBuilder::stats`.
+    pub fn stats(&self) -> Option<PoolStats> {
+        self.inner.stats()
+    }
+
+    #[cfg(feature = "metrics")]
+    pub fn publish_metrics(&self, metrics: &PoolMetrics) {
+        if let Some(stats) = self.stats() {
+            metrics.publish(&stats, false);
+        }
+    }
+
+    /// Returns the distribution of hold times if the pool has been built with
+    /// `PoolBuilder::hold_histogram`.
+    pub fn hold_histogram(&self) -> Option<HoldHistogram> {
+        self.inner.holds.as_ref()
+            .map(|holds| holds.snapshot())
+    }
+
+    /// Lists values which have been lent and not returned back yet, the oldest first.
+    #[cfg(feature = "leak-detection")]
+    pub fn outstanding_report(&self) -> Vec<OutstandingHandle> {
+        self.inner.leaks.report(self.inner.params.clock.now())
+    }
+
+    /// Drops idle values until at most `idle_target` remain. Returns the number of values dropped.
+    pub fn trim_to(&self, idle_target: usize) -> usize {
+        self.inner.trim_idle_to(idle_target)
+    }
+
+    /// Drops all idle values. Returns the number of values dropped. The whole idle list is
+    /// taken at once, so lends running meanwhile make new values.
+    pub fn clear(&self) -> usize {
+        self.inner.retain_idle(|_value, _returned_at| false)
+    }
+
+    /// Drops idle values, the largest size class first, until their total weight is at most
+    /// `weight_target`. Returns the number of values dropped.
+    pub(crate) fn trim_weight_to(&self, weight_target: usize) -> usize {
+        self.inner.trim_weight_to(weight_target)
+    }
+
+    /// Spawns a background thread applying idle ttl and max idle policies every `interval`.
+    /// Use `FactoryPool::spawn_maintainer` to keep `min_idle` values as well.
+    pub fn spawn_maintainer(&self, interval: Duration) -> io::Result<PoolMaintainer> where T: Send + Sync + 'static {
+        PoolMaintainer::spawn(Arc::downgrade(&self.inner), interval, None::<fn() -> T>)
+    }
+
+    /// Same as `lend`, but takes idle values only from size classes starting with `min_class`.
+    pub(crate) fn lend_from_class<F>(&self, min_class: usize, make_value: F) -> Unique<T> where F: FnOnce() -> T {
+        self.inner.slots.acquire(None);
+        let (unique, _discarded) = self.lend_acquired_checked(min_class, make_value, |_| true);
+        unique
+    }
+
+    /// Same as `try_lend`, but takes idle values only from size classes starting with `min_class`.
+    pub(crate) fn try_lend_from_class<E, F>(&self, min_class: usize, make_value: F) -> Result<Unique<T>, E> where F: FnOnce() -> Result<T, E> {
+        self.inner.slots.acquire(None);
+        let (unique, _discarded) = self.try_lend_acquired_checked(min_class, make_value, |_| true)?;
+        Ok(unique)
+    }
+
+    fn lend_acquired<F>(&self, make_value: F) -> Unique<T> where F: FnOnce() -> T {
+        let (unique, _discarded) = self.lend_acquired_checked(0, make_value, |_| true);
+        unique
+    }
+
+    fn lend_acquired_checked<F, H>(&self, min_class: usize, make_value: F, is_healthy: H) -> (Unique<T>, usize) where F: FnOnce() -> T, H: FnMut(&T) -> bool {
+        match self.try_lend_acquired_checked(min_class, || Ok::<_, Infallible>(make_value()), is_healthy) {
+            Ok(lent) =>
+                lent,
+            Err(never) =>
+                match never { },
+        }
+    }
+
+    fn try_lend_acquired_checked<E, F, H>(
+        &self,
+        min_class: usize,
+        make_value: F,
+        mut is_healthy: H,
+    )
+        -> Result<(Unique<T>, usize), E>
+    where F: FnOnce() -> Result<T, E>,
+          H: FnMut(&T) -> bool,
+    {
+        let slot_guard = wait::SlotGuard::new(&self.inner.slots);
+        let mut discarded = 0;
+        let (value, object_id, hit) = loop {
+            match self.inner.pop_idle_from(min_class) {
+                Some((value, object_id)) if is_healthy(&value) =>
+                    break (value, object_id, true),
+                Some(..) =>
+                    discarded += 1,
+                None =>
+                    break (make_value()?, next_object_id(), false),
+            }
+        };
+        slot_guard.disarm();
+        self.inner.lent(object_id, hit);
+        Ok((Unique { inner: Inner::new(value, object_id, self.inner.clone()), }, discarded))
+    }
+}
+
+pub struct FactoryPool<T, F> {
+    pool: Pool<T>,
+    factory: Arc<F>,
+}
+
+impl<T, F> Clone for FactoryPool<T, F> {
+    fn clone(&self) -> FactoryPool<T, F> {
+        FactoryPool {
+            pool: self.pool.clone(),
+            factory: self.factory.clone(),
+        }
+    }
+}
+
+impl<T, F> fmt::Debug for FactoryPool<T, F> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("FactoryPool")
+            .field("pool", &self.pool)
+            .finish()
+    }
+}
+
+impl<T, F> FactoryPool<T, F> where F: Fn() -> T {
+    pub fn new(pool: Pool<T>, factory: F) -> FactoryPool<T, F> {
+        FactoryPool { pool, factory: Arc::new(factory), }
+    }
+
+    pub fn pool(&self) -> &Pool<T> {
+        &self.pool
+    }
+
+    pub fn lend(&self) -> Unique<T> {
+        self.pool.lend(&*self.factory)
+    }
+
+    pub fn lend_blocking(&self, timeout: Duration) -> Result<Unique<T>, LendTimeout> {
+        self.pool.lend_blocking(timeout, &*self.factory)
+    }
+
+    pub async fn lend_async(&self) -> Unique<T> {
+        self.pool.lend_async(&*self.factory).await
+    }
+
+    pub fn lend_checked<H>(&self, is_healthy: H) -> (Unique<T>, usize) where H: FnMut(&T) -> bool {
+        self.pool.lend_checked(&*self.factory, is_healthy)
+    }
+
+    pub fn prefill(&self, count: usize) -> usize {
+        self.pool.prefill(count, &*self.factory)
+    }
+
+    pub fn maintain(&self) -> usize {
+        self.pool.maintain(&*self.factory)
+    }
+
+    /// Spawns a background thread applying idle ttl, max idle and min idle policies every `interval`.
+    pub fn spawn_maintainer(&self, interval: Duration) -> io::Result<PoolMaintainer>
+    where T: Send + Sync + 'static,
+          F: Send + Sync + 'static,
+    {
+        let factory = self.factory.clone();
+        PoolMaintainer::spawn(Arc::downgrade(&self.pool.inner), interval, Some(move || factory()))
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct LendTimeout;
+
+impl fmt::Display for LendTimeout {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "timed out waiting for a value to be returned to the pool")
+    }
+}
+
+impl error::Error for LendTimeout { }
+
+#[derive(Debug)]
+pub struct PoolBuilder<T> {
+    params: Params<T>,
+}
+
+impl<T> Default for PoolBuilder<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> PoolBuilder<T> {
+    pub fn new() -> PoolBuilder<T> {
+        PoolBuilder {
+            params: Params::default(),
+        }
+    }
+
+    pub fn max_idle(mut self, max_idle: usize) -> Self {
+        self.params.max_idle = Some(max_idle);
+        self
+    }
+
+    /// Keeps at least `min_idle` values in idle list, see `Pool::maintain`.
+    pub fn min_idle(mut self, min_idle: usize) -> Self {
+        self.params.min_idle = Some(min_idle);
+        self
+    }
+
+    /// Limits the number of values lent out at once, including those frozen into `Shared`.
+    pub fn max_outstanding(mut self, max_outstanding: usize) -> Self {
+        self.params.max_outstanding = Some(max_outstanding);
+        self
+    }
+
+    /// Resets values with `Recycle::recycle` on the returning thread, before they go back to
+    /// idle list.
+    pub fn recycling(mut self) -> Self where T: Recycle {
+        self.params.recycle = Some(T::recycle);
+        self
+    }
+
+    /// Drops idle values which have not been lent for longer than `idle_ttl`. Eviction
+    /// happens lazily on `lend` and on value return, or explicitly with `Pool::evict_expired`.
+    pub fn idle_ttl(mut self, idle_ttl: Duration) -> Self {
+        self.params.idle_ttl = Some(idle_ttl);
+        self
+    }
+
+    pub fn clock(mut self, clock: Arc<dyn clock::Clock>) -> Self {
+        self.params.clock = clock;
+        self
+    }
+
+    /// Charges idle values to `budget` by their `Weight`, see `MemoryBudget`.
+    pub fn memory_budget(mut self, budget: budget::MemoryBudget) -> Self where T: budget::Weight + Send + Sync + 'static {
+        self.params.weigh = Some(T::weight);
+        self.params.memory_budget = Some(budget::Member::new(budget));
+        self.params.register = Some(registry::downgrade::<T>);
+        self
+    }
+
+    /// Sets how many idle values are kept on `purge_all` for each pressure level, the default
+    /// `PurgePolicy` otherwise.
+    pub fn purge_policy(mut self, purge_policy: PurgePolicy) -> Self where T: Send + Sync + 'static {
+        self.params.purge_policy = purge_policy;
+        self.params.register = Some(registry::downgrade::<T>);
+        self
+    }
+
+    pub(crate) fn weigh(mut self, weigh: fn(&T) -> usize) -> Self {
+        self.params.weigh = Some(weigh);
+        self
+    }
+
+    pub(crate) fn size_classes(mut self, size_classes: usize, classify: fn(&T) -> usize) -> Self {
+        self.params.size_classes = size_classes;
+        self.params.classify = Some(classify);
+        self
+    }
+
+    /// Checks values when they are returned back: those for which `return_filter` returns `false`
+    /// are dropped instead of being reused.
+    pub fn return_filter<F>(mut self, return_filter: F) -> Self where F: Fn(&T) -> bool + Send + Sync + 'static {
+        self.params.return_filter = Some(Box::new(return_filter));
+        self
+    }
+
+    /// Enables pool counters, see `Pool::stats`. They are updated with relaxed atomics so it is
+    /// cheap to keep them on.
+    pub fn stats(mut self) -> Self {
+        self.params.stats = true;
+        self
+    }
+
+    /// Reports values which come back later than `hold_threshold` after they have been lent,
+    /// with `on_long_hold` callback or as a `tracing` warning if there is none. Without both
+    /// the callback and the `tracing` feature nothing is reported, and lend times are not tracked
+    /// unless `hold_histogram` is on.
+    pub fn hold_threshold(mut self, hold_threshold: Duration) -> Self {
+        self.params.hold_threshold = Some(hold_threshold);
+        self
+    }
+
+    pub fn on_long_hold<F>(mut self, on_long_hold: F) -> Self where F: Fn(&T, Duration) + Send + Sync + 'static {
+        self.params.on_long_hold = Some(Box::new(on_long_hold));
+        self
+    }
+
+    /// Enables hold times histogram, see `Pool::hold_histogram`.
+    pub fn hold_histogram(mut self) -> Self {
+        self.params.hold_histogram = true;
+        self
+    }
+
+    /// Captures a backtrace on every lend to be shown in `Pool::outstanding_report`. This is
+    /// expensive and is meant for debugging only.
+    #[cfg(feature = "leak-detection")]
+    pub fn lend_backtraces(mut self) -> Self {
+        self.params.leak_detection.lend_backtraces = true;
+        self
+    }
+
+    /// Sets what happens when the last `Pool` handle is dropped while values are still lent.
+    #[cfg(feature = "leak-detection")]
+    pub fn on_leak(mut self, on_leak: LeakAction) -> Self {
+        self.params.leak_detection.on_leak = on_leak;
+        self
+    }
+
+    /// Builds the pool and registers it, so it is trimmed by `purge_all` according to its
+    /// `PurgePolicy`.
+    pub fn build(mut self) -> Pool<T> where T: Send + Sync + 'static {
+        self.params.register = Some(registry::downgrade::<T>);
+        self.build_local()
+    }
+
+    /// Same as `build`, for values which are not `Send + Sync`. The pool is not registered
+    /// unless built with a `MemoryBudget` or a `PurgePolicy`, so `purge_all` never reaches it.
+    pub fn build_local(self) -> Pool<T> {
+        let pool = Pool::attach(Arc::new(PoolHead::new(self.params)));
+        if let Some(register) = pool.inner.params.register {
+            let pool_head = register(&pool.inner);
+            if let (Some(member), Some(registered)) = (&pool.inner.params.memory_budget, pool_head.upgrade()) {
+                member.attach(Arc::downgrade(&registered.budgeted()));
+            }
+            registry::register(pool_head);
+        }
+        pool
+    }
+
+    /// Same as `build`, but also lists the pool in `registry::snapshot` under `name`.
+    pub fn build_named<S>(mut self, name: S) -> Pool<T> where S: Into<String>, T: Send + Sync + 'static {
+        self.params.name = Some(name.into());
+        self.build()
+    }
+
+    pub fn build_with_factory<F>(self, factory: F) -> FactoryPool<T, F> where F: Fn() -> T, T: Send + Sync + 'static {
+        FactoryPool::new(self.build(), factory)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        mem::drop,
+        thread,
+        panic,
+        pin::{
+            pin,
+            Pin,
+        },
+        future::{
+            Future,
+        },
+        task::{
+            Poll,
+            Wake,
+            Waker,
+            Context,
+        },
+        time::{
+            Duration,
+            Instant,
+        },
+        sync::{
+            Arc,
+            Mutex,
+            Barrier,
+            atomic::{
+                Ordering,
+                AtomicBool,
+                AtomicUsize,
+            },
+        },
+    };
+
+    use crossbeam_epoch as epoch;
+
+    use super::{
+        Pool,
+        PoolStats,
+        LendTimeout,
+        super::clock::Clock,
+    };
+
+    struct ThreadWaker {
+        thread: thread::Thread,
+        wakes: AtomicUsize,
+    }
+
+    impl Wake for ThreadWaker {
+        fn wake(self: Arc<Self>) {
+            self.wake_by_ref();
+        }
+
+        fn wake_by_ref(self: &Arc<Self>) {
+            self.wakes.fetch_add(1, Ordering::SeqCst);
+            self.thread.unpark();
+        }
+    }
+
+    fn thread_waker() -> (Arc<ThreadWaker>, Waker) {
+        let thread_waker = Arc::new(ThreadWaker {
+            thread: thread::current(),
+            wakes: AtomicUsize::new(0),
+        });
+        (thread_waker.clone(), Waker::from(thread_waker))
+    }
+
+    struct ManualClock {
+        now: Mutex<Instant>,
+    }
+
+    impl ManualClock {
+        fn new() -> Arc<ManualClock> {
+            Arc::new(ManualClock { now: Mutex::new(Instant::now()), })
+        }
+
+        fn advance(&self, duration: Duration) {
+            *self.now.lock().unwrap() += duration;
+        }
+    }
+
+    impl Clock for ManualClock {
+        fn now(&self) -> Instant {
+            *self.now.lock().unwrap()
+        }
+    }
+
+    struct YieldOnce(bool);
+
+    impl Future for YieldOnce {
+        type Output = ();
+
+        fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+            if self.0 {
+                Poll::Ready(())
+            } else {
+                self.0 = true;
+                cx.waker().wake_by_ref();
+                Poll::Pending
+            }
+        }
+    }
+
+    fn block_on<F>(future: F) -> F::Output where F: Future {
+        let (_thread_waker, waker) = thread_waker();
+        let mut cx = Context::from_waker(&waker);
+        let mut future = pin!(future);
         loop {
-            let head = self.inner.head.load(Ordering::Acquire, &guard);
-            match unsafe { head.as_ref() } {
-                Some(entry) => {
-                    let next = entry.next.load(Ordering::Relaxed, &guard);
-                    if self.inner.head.compare_exchange(head, next, Ordering::Relaxed, Ordering::Relaxed, &guard).is_ok() {
-                        unsafe {
-                            guard.defer_destroy(head);
-                            let value = ManuallyDrop::into_inner(
-                                ptr::read(&entry.value),
-                            );
-                            return Unique { inner: Inner::new(value, self.inner.clone()), };
+            match future.as_mut().poll(&mut cx) {
+                Poll::Ready(output) =>
+                    return output,
+                Poll::Pending =>
+                    thread::park(),
+            }
+        }
+    }
+
+    fn idle_list_len<T>(pool: &Pool<T>) -> usize {
+        let guard = epoch::pin();
+        let mut len = 0;
+        for head in pool.inner.heads.iter() {
+            let mut current = head.load(Ordering::Acquire, &guard);
+            while let Some(entry) = unsafe { current.as_ref() } {
+                len += 1;
+                current = entry.next.load(Ordering::Acquire, &guard);
+            }
+        }
+        len
+    }
+
+    #[test]
+    fn max_idle_single_thread() {
+        let pool = Pool::with_max_idle(2);
+
+        let values: Vec<_> = (0 .. 5).map(|index| pool.lend(|| index)).collect();
+        drop(values);
+        assert_eq!(pool.inner.idle.load(Ordering::SeqCst), 2);
+        assert_eq!(idle_list_len(&pool), 2);
+
+        let value_a = pool.lend(|| 100);
+        let value_b = pool.lend(|| 100);
+        let value_c = pool.lend(|| 100);
+        assert_ne!(*value_a, 100);
+        assert_ne!(*value_b, 100);
+        assert_eq!(*value_c, 100);
+        assert_eq!(pool.inner.idle.load(Ordering::SeqCst), 0);
+    }
+
+    #[test]
+    fn max_idle_concurrent_returns() {
+        const THREADS: usize = 8;
+        const VALUES_PER_THREAD: usize = 64;
+        const MAX_IDLE: usize = 16;
+
+        struct Sample {
+            drop_counter: Arc<AtomicUsize>,
+        }
+
+        impl Drop for Sample {
+            fn drop(&mut self) {
+                self.drop_counter.fetch_add(1, Ordering::SeqCst);
+            }
+        }
+
+        let pool = Pool::with_max_idle(MAX_IDLE);
+        let make_counter = Arc::new(AtomicUsize::new(0));
+        let drop_counter = Arc::new(AtomicUsize::new(0));
+        let barrier = Arc::new(Barrier::new(THREADS));
+        let done = Arc::new(AtomicBool::new(false));
+
+        let watcher = {
+            let pool = pool.clone();
+            let done = done.clone();
+            thread::spawn(move || {
+                while !done.load(Ordering::SeqCst) {
+                    assert!(pool.inner.idle.load(Ordering::SeqCst) <= MAX_IDLE);
+                    assert!(idle_list_len(&pool) <= MAX_IDLE);
+                }
+            })
+        };
+
+        let workers: Vec<_> = (0 .. THREADS)
+            .map(|_| {
+                let pool = pool.clone();
+                let make_counter = make_counter.clone();
+                let drop_counter = drop_counter.clone();
+                let barrier = barrier.clone();
+                thread::spawn(move || {
+                    for _ in 0 .. 16 {
+                        let values: Vec<_> = (0 .. VALUES_PER_THREAD)
+                            .map(|_| pool.lend(|| {
+                                make_counter.fetch_add(1, Ordering::SeqCst);
+                                Sample { drop_counter: drop_counter.clone(), }
+                            }))
+                            .collect();
+                        barrier.wait();
+                        drop(values);
+                    }
+                })
+            })
+            .collect();
+        for worker in workers {
+            worker.join().unwrap();
+        }
+        done.store(true, Ordering::SeqCst);
+        watcher.join().unwrap();
+
+        let idle = pool.inner.idle.load(Ordering::SeqCst);
+        assert_eq!(idle, MAX_IDLE);
+        assert_eq!(idle_list_len(&pool), MAX_IDLE);
+        assert_eq!(make_counter.load(Ordering::SeqCst) - drop_counter.load(Ordering::SeqCst), idle);
+
+        drop(pool);
+        assert_eq!(make_counter.load(Ordering::SeqCst), drop_counter.load(Ordering::SeqCst));
+    }
+
+    #[test]
+    fn max_outstanding_counts_shared() {
+        let pool = Pool::builder().max_outstanding(2).build();
+
+        let value_a = pool.lend(|| 0);
+        let value_b = pool.lend(|| 1);
+        assert_eq!(pool.lend_blocking(Duration::from_millis(10), || 2), Err(LendTimeout));
+
+        let shared_a = value_a.freeze();
+        let shared_a_cloned = shared_a.clone();
+        assert_eq!(pool.lend_blocking(Duration::from_millis(10), || 2), Err(LendTimeout));
+
+        drop(shared_a);
+        assert_eq!(pool.lend_blocking(Duration::from_millis(10), || 2), Err(LendTimeout));
+
+        let releaser = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(50));
+            drop(shared_a_cloned);
+        });
+        let value_c = pool.lend_blocking(Duration::from_secs(10), || 2).unwrap();
+        assert_eq!(*value_c, 0);
+        releaser.join().unwrap();
+
+        drop(value_b);
+        drop(value_c);
+        assert_eq!(pool.inner.slots.outstanding(), 0);
+    }
+
+    #[test]
+    fn max_outstanding_concurrent() {
+        const THREADS: usize = 8;
+        const MAX_OUTSTANDING: usize = 3;
+
+        let pool = Pool::builder().max_outstanding(MAX_OUTSTANDING).build();
+        let live = Arc::new(AtomicUsize::new(0));
+        let timeouts = Arc::new(AtomicUsize::new(0));
+
+        let workers: Vec<_> = (0 .. THREADS)
+            .map(|_| {
+                let pool = pool.clone();
+                let live = live.clone();
+                let timeouts = timeouts.clone();
+                thread::spawn(move || {
+                    for round in 0 .. 200 {
+                        let value = if round % 2 == 0 {
+                            pool.lend(|| 0)
+                        } else {
+                            match pool.lend_blocking(Duration::from_micros(50), || 0) {
+                                Ok(value) =>
+                                    value,
+                                Err(LendTimeout) => {
+                                    timeouts.fetch_add(1, Ordering::SeqCst);
+                                    continue;
+                                },
+                            }
+                        };
+                        assert!(live.fetch_add(1, Ordering::SeqCst) < MAX_OUTSTANDING);
+                        thread::yield_now();
+                        live.fetch_sub(1, Ordering::SeqCst);
+                        if round % 3 == 0 {
+                            drop(value.freeze());
+                        } else {
+                            drop(value);
                         }
                     }
-                },
-                None =>
-                    return Unique { inner: Inner::new(make_value(), self.inner.clone()), },
-            }
+                })
+            })
+            .collect();
+        for worker in workers {
+            worker.join().unwrap();
+        }
+
+        assert_eq!(pool.inner.slots.outstanding(), 0);
+        assert!(pool.inner.idle.load(Ordering::SeqCst) <= MAX_OUTSTANDING);
+    }
+
+    #[test]
+    fn lend_async_fifo() {
+        let pool = Pool::builder().max_outstanding(1).build();
+        let (thread_waker, waker) = thread_waker();
+        let mut cx = Context::from_waker(&waker);
+
+        let value = block_on(pool.lend_async(|| 0));
+
+        let mut lend_a = Box::pin(pool.lend_async(|| 1));
+        let mut lend_b = Box::pin(pool.lend_async(|| 2));
+        assert!(lend_a.as_mut().poll(&mut cx).is_pending());
+        assert!(lend_b.as_mut().poll(&mut cx).is_pending());
+        assert_eq!(thread_waker.wakes.load(Ordering::SeqCst), 0);
+
+        drop(value);
+        assert_eq!(thread_waker.wakes.load(Ordering::SeqCst), 1);
+        assert!(lend_b.as_mut().poll(&mut cx).is_pending());
+        let value_a = match lend_a.as_mut().poll(&mut cx) {
+            Poll::Ready(value) =>
+                value,
+            Poll::Pending =>
+                panic!("first waiter should be served first"),
+        };
+        assert_eq!(*value_a, 0);
+
+        drop(value_a);
+        assert_eq!(thread_waker.wakes.load(Ordering::SeqCst), 2);
+        match lend_b.as_mut().poll(&mut cx) {
+            Poll::Ready(value) =>
+                assert_eq!(*value, 0),
+            Poll::Pending =>
+                panic!("second waiter should be served after the first one"),
+        }
+    }
+
+    #[test]
+    fn lend_async_cancel() {
+        let pool = Pool::builder().max_outstanding(1).build();
+        let (_thread_waker, waker) = thread_waker();
+        let mut cx = Context::from_waker(&waker);
+
+        let value = block_on(pool.lend_async(|| 0));
+
+        // cancelled while still waiting
+        let mut lend_a = Box::pin(pool.lend_async(|| 1));
+        assert!(lend_a.as_mut().poll(&mut cx).is_pending());
+        drop(lend_a);
+
+        // cancelled after the slot has been handed over but before it was polled again
+        let mut lend_b = Box::pin(pool.lend_async(|| 2));
+        let mut lend_c = Box::pin(pool.lend_async(|| 3));
+        assert!(lend_b.as_mut().poll(&mut cx).is_pending());
+        assert!(lend_c.as_mut().poll(&mut cx).is_pending());
+        drop(value);
+        drop(lend_b);
+
+        match lend_c.as_mut().poll(&mut cx) {
+            Poll::Ready(value) =>
+                assert_eq!(*value, 0),
+            Poll::Pending =>
+                panic!("slot of a cancelled waiter should be passed on"),
+        }
+        drop(lend_c);
+        assert_eq!(pool.inner.slots.outstanding(), 0);
+        assert_eq!(*block_on(pool.lend_async(|| 4)), 0);
+    }
+
+    #[test]
+    fn lend_async_threads() {
+        const THREADS: usize = 4;
+        const MAX_OUTSTANDING: usize = 2;
+
+        let pool = Pool::builder().max_outstanding(MAX_OUTSTANDING).build();
+        let live = Arc::new(AtomicUsize::new(0));
+
+        let workers: Vec<_> = (0 .. THREADS)
+            .map(|_| {
+                let pool = pool.clone();
+                let live = live.clone();
+                thread::spawn(move || {
+                    for _ in 0 .. 200 {
+                        let value = block_on(pool.lend_async(|| 0));
+                        assert!(live.fetch_add(1, Ordering::SeqCst) < MAX_OUTSTANDING);
+                        thread::yield_now();
+                        live.fetch_sub(1, Ordering::SeqCst);
+                        drop(value);
+                    }
+                })
+            })
+            .collect();
+        for worker in workers {
+            worker.join().unwrap();
+        }
+        assert_eq!(pool.inner.slots.outstanding(), 0);
+    }
+
+    #[test]
+    fn recycling() {
+        let pool = Pool::new_recycling();
+
+        let mut value = pool.lend(Vec::new);
+        value.extend_from_slice(&[0, 1, 2, 3]);
+        let capacity = value.capacity();
+        drop(value);
+
+        let value = pool.lend(Vec::new);
+        assert!(value.is_empty());
+        assert_eq!(value.capacity(), capacity);
+
+        let mut value = pool.lend(Vec::new);
+        value.push(4);
+        drop(value.freeze());
+        assert_eq!(&*pool.lend(|| vec![5]), &[] as &[u8]);
+    }
+
+    #[test]
+    fn return_filter() {
+        let pool = Pool::with_return_filter(|value: &Vec<u8>| value.len() < 4);
+
+        let mut value_a = pool.lend(Vec::new);
+        value_a.extend_from_slice(&[0, 1, 2, 3]);
+        let mut value_b = pool.lend(Vec::new);
+        value_b.extend_from_slice(&[0, 1]);
+        drop(value_a);
+        drop(value_b.freeze());
+        assert_eq!(pool.inner.idle.load(Ordering::SeqCst), 1);
+
+        let value_a = pool.lend(Vec::new);
+        let value_b = pool.lend(Vec::new);
+        assert_eq!(&*value_a, &[0, 1]);
+        assert_eq!(&*value_b, &[] as &[u8]);
+    }
+
+    #[test]
+    fn lend_checked() {
+        let pool = Pool::new();
+
+        let values: Vec<_> = (0 .. 5).map(|index| pool.lend(|| index)).collect();
+        drop(values);
+
+        // idle list is [4, 3, 2, 1, 0]
+        let (value, discarded) = pool.lend_checked(|| 100, |value| value % 2 == 1);
+        assert_eq!(*value, 3);
+        assert_eq!(discarded, 1);
+
+        let (value, discarded) = pool.lend_checked(|| 100, |value| *value > 2);
+        assert_eq!(*value, 100);
+        assert_eq!(discarded, 3);
+        assert_eq!(pool.inner.idle.load(Ordering::SeqCst), 0);
+    }
+
+    #[test]
+    fn try_lend() {
+        let pool = Pool::builder().max_outstanding(1).build();
+
+        assert_eq!(pool.try_lend(|| Err::<i32, _>("failed")).unwrap_err(), "failed");
+        assert_eq!(pool.inner.slots.outstanding(), 0);
+
+        let value = pool.try_lend(|| Ok::<_, &str>(0)).unwrap();
+        assert_eq!(*value, 0);
+        drop(value);
+
+        let value = pool.try_lend(|| Err("unreachable")).unwrap();
+        assert_eq!(*value, 0);
+        drop(value);
+
+        assert_eq!(pool.inner.slots.outstanding(), 0);
+        assert_eq!(pool.inner.idle.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn return_panic_releases_slot() {
+        let pool = Pool::builder()
+            .max_outstanding(1)
+            .return_filter(|value| if *value == 0 { panic!("return filter failed") } else { true })
+            .build();
+        let value = pool.lend(|| 0);
+        assert!(panic::catch_unwind(panic::AssertUnwindSafe(|| drop(value))).is_err());
+        assert_eq!(pool.inner.slots.outstanding(), 0);
+
+        let value = pool.lend_blocking(Duration::from_millis(100), || 1).unwrap();
+        assert_eq!(*value, 1);
+    }
+
+    #[test]
+    fn lend_with_async() {
+        let pool = Pool::builder().max_outstanding(1).build();
+
+        let value = block_on(pool.lend_with_async(|| async { YieldOnce(false).await; 0 }));
+        assert_eq!(*value, 0);
+        drop(value);
+
+        let value = block_on(pool.lend_with_async(|| async { unreachable!() }));
+        assert_eq!(*value, 0);
+        drop(value);
+
+        let value = block_on(pool.try_lend_with_async(|| async { Err::<i32, _>("unreachable") })).unwrap();
+        assert_eq!(*value, 0);
+        drop(value);
+        assert_eq!(pool.inner.slots.outstanding(), 0);
+    }
+
+    #[test]
+    fn lend_with_async_miss() {
+        let pool = Pool::builder().max_outstanding(1).build();
+        let (_thread_waker, waker) = thread_waker();
+        let mut cx = Context::from_waker(&waker);
+
+        let result = block_on(pool.try_lend_with_async(|| async { YieldOnce(false).await; Err::<i32, _>("failed") }));
+        assert_eq!(result.unwrap_err(), "failed");
+        assert_eq!(pool.inner.slots.outstanding(), 0);
+
+        // cancelled while awaiting a factory
+        let mut lend = Box::pin(pool.lend_with_async(|| async { YieldOnce(false).await; 2 }));
+        assert!(lend.as_mut().poll(&mut cx).is_pending());
+        assert_eq!(pool.inner.slots.outstanding(), 1);
+        drop(lend);
+        assert_eq!(pool.inner.slots.outstanding(), 0);
+        assert_eq!(pool.inner.idle.load(Ordering::SeqCst), 0);
+
+        let value = block_on(pool.lend_with_async(|| async { 1 }));
+        assert_eq!(*value, 1);
+    }
+
+    #[test]
+    fn factory_pool() {
+        let make_counter = Arc::new(AtomicUsize::new(0));
+        let pool = {
+            let make_counter = make_counter.clone();
+            Pool::with_factory(move || make_counter.fetch_add(1, Ordering::SeqCst))
+        };
+        let pool_cloned = pool.clone();
+
+        let value_a = pool.lend();
+        let value_b = pool_cloned.lend();
+        assert_eq!((*value_a, *value_b), (0, 1));
+        drop(value_a);
+
+        let value_a = pool_cloned.lend();
+        assert_eq!(*value_a, 0);
+        assert_eq!(*block_on(pool.lend_async()), 2);
+        assert_eq!(make_counter.load(Ordering::SeqCst), 3);
+    }
+
+    #[test]
+    fn prefill() {
+        let pool = Pool::with_max_idle(4);
+        let mut make_counter = 0;
+
+        assert_eq!(pool.prefill(3, || { make_counter += 1; make_counter }), 3);
+        assert_eq!(pool.inner.idle.load(Ordering::SeqCst), 3);
+        assert_eq!(pool.prefill(3, || { make_counter += 1; make_counter }), 1);
+        assert_eq!(make_counter, 4);
+        assert_eq!(idle_list_len(&pool), 4);
+
+        let value = pool.lend(|| unreachable!());
+        assert_eq!(*value, 4);
+    }
+
+    #[test]
+    fn maintain_min_idle() {
+        let pool = Pool::builder()
+            .min_idle(3)
+            .max_idle(8)
+            .build_with_factory(|| 0);
+
+        assert_eq!(pool.maintain(), 3);
+        assert_eq!(pool.maintain(), 0);
+
+        let values: Vec<_> = (0 .. 5).map(|_| pool.lend()).collect();
+        assert_eq!(pool.pool().inner.idle.load(Ordering::SeqCst), 0);
+        assert_eq!(pool.maintain(), 3);
+        drop(values);
+        assert_eq!(pool.pool().inner.idle.load(Ordering::SeqCst), 8);
+        assert_eq!(pool.maintain(), 0);
+
+        let pool = Pool::builder().min_idle(16).max_idle(2).build();
+        assert_eq!(pool.maintain(|| 0), 2);
+        assert_eq!(Pool::new().maintain(|| 0), 0);
+    }
+
+    #[test]
+    fn idle_ttl_lend() {
+        let clock = ManualClock::new();
+        let pool = Pool::builder()
+            .idle_ttl(Duration::from_secs(10))
+            .clock(clock.clone())
+            .build();
+
+        let value_a = pool.lend(|| 0);
+        let value_b = pool.lend(|| 1);
+        drop(value_a);
+        clock.advance(Duration::from_secs(5));
+        drop(value_b);
+        clock.advance(Duration::from_secs(6));
+
+        let value_b = pool.lend(|| 2);
+        assert_eq!(*value_b, 1);
+        let value_c = pool.lend(|| 2);
+        assert_eq!(*value_c, 2);
+        assert_eq!(pool.inner.idle.load(Ordering::SeqCst), 0);
+    }
+
+    #[test]
+    fn idle_ttl_evict_expired() {
+        let clock = ManualClock::new();
+        let pool = Pool::builder()
+            .idle_ttl(Duration::from_secs(10))
+            .clock(clock.clone())
+            .build();
+
+        let mut values: Vec<_> = (0 .. 3).map(|index| pool.lend(|| index)).collect();
+        let value_c = values.pop().unwrap();
+        drop(values);
+        clock.advance(Duration::from_secs(5));
+        // previous returns made the next lazy eviction due only in 10 seconds
+        drop(value_c);
+        assert_eq!(pool.prefill(1, || 3), 1);
+        assert_eq!(pool.evict_expired(), 0);
+
+        clock.advance(Duration::from_secs(6));
+        assert_eq!(pool.inner.idle.load(Ordering::SeqCst), 4);
+        assert_eq!(pool.evict_expired(), 2);
+        assert_eq!(pool.inner.idle.load(Ordering::SeqCst), 2);
+        assert_eq!(idle_list_len(&pool), 2);
+
+        let value_a = pool.lend(|| 100);
+        let value_b = pool.lend(|| 100);
+        let value_c = pool.lend(|| 100);
+        assert_eq!((*value_a, *value_b, *value_c), (3, 2, 100));
+    }
+
+    #[test]
+    fn idle_ttl_lazy_on_return() {
+        let clock = ManualClock::new();
+        let pool = Pool::builder()
+            .idle_ttl(Duration::from_secs(10))
+            .clock(clock.clone())
+            .build();
+
+        let value_a = pool.lend(|| 0);
+        let value_b = pool.lend(|| 1);
+        drop(value_a);
+        clock.advance(Duration::from_secs(11));
+        drop(value_b);
+        assert_eq!(pool.inner.idle.load(Ordering::SeqCst), 1);
+        assert_eq!(*pool.lend(|| 2), 1);
+    }
+
+    fn wait_for<F>(mut condition: F) where F: FnMut() -> bool {
+        let deadline = Instant::now() + Duration::from_secs(10);
+        while !condition() {
+            assert!(Instant::now() < deadline, "condition has not been met in time");
+            thread::sleep(Duration::from_millis(1));
         }
     }
+
+    #[test]
+    fn maintainer() {
+        let clock = ManualClock::new();
+        let make_counter = Arc::new(AtomicUsize::new(0));
+        let pool = {
+            let make_counter = make_counter.clone();
+            Pool::builder()
+                .min_idle(2)
+                .idle_ttl(Duration::from_secs(10))
+                .clock(clock.clone())
+                .build_with_factory(move || make_counter.fetch_add(1, Ordering::SeqCst))
+        };
+        let maintainer = pool.spawn_maintainer(Duration::from_millis(1)).unwrap();
+
+        wait_for(|| make_counter.load(Ordering::SeqCst) == 2);
+        let value = pool.lend();
+        wait_for(|| make_counter.load(Ordering::SeqCst) == 3);
+
+        clock.advance(Duration::from_secs(11));
+        wait_for(|| make_counter.load(Ordering::SeqCst) == 5);
+        assert_eq!(pool.pool().inner.idle.load(Ordering::SeqCst), 2);
+
+        drop(pool);
+        thread::sleep(Duration::from_millis(10));
+        assert!(!maintainer.is_finished());
+        drop(value);
+        maintainer.join().unwrap();
+    }
+
+    #[test]
+    fn maintainer_stop() {
+        let pool = Pool::<u8>::with_max_idle(4);
+        let maintainer = pool.spawn_maintainer(Duration::from_secs(3600)).unwrap();
+        maintainer.stop().unwrap();
+    }
+
+    #[test]
+    fn trim_to_and_clear() {
+        let pool = Pool::new();
+        assert_eq!(pool.prefill(8, || 0), 8);
+
+        assert_eq!(pool.trim_to(10), 0);
+        assert_eq!(pool.trim_to(5), 3);
+        assert_eq!(idle_list_len(&pool), 5);
+        assert_eq!(pool.clear(), 5);
+        assert_eq!(idle_list_len(&pool), 0);
+        assert_eq!(pool.inner.idle.load(Ordering::SeqCst), 0);
+        assert_eq!(pool.clear(), 0);
+    }
+
+    #[test]
+    fn trim_concurrent_lend() {
+        const THREADS: usize = 4;
+
+        let pool = Pool::new();
+        let done = Arc::new(AtomicBool::new(false));
+
+        let workers: Vec<_> = (0 .. THREADS)
+            .map(|_| {
+                let pool = pool.clone();
+                let done = done.clone();
+                thread::spawn(move || {
+                    while !done.load(Ordering::SeqCst) {
+                        let values: Vec<_> = (0 .. 8).map(|_| pool.lend(|| 0)).collect();
+                        drop(values);
+                    }
+                })
+            })
+            .collect();
+        for _ in 0 .. 1000 {
+            pool.trim_to(4);
+            pool.clear();
+        }
+        done.store(true, Ordering::SeqCst);
+        for worker in workers {
+            worker.join().unwrap();
+        }
+
+        assert_eq!(pool.inner.idle.load(Ordering::SeqCst), idle_list_len(&pool));
+        assert_eq!(pool.inner.slots.outstanding(), 0);
+    }
+
+    #[test]
+    fn stats() {
+        assert_eq!(Pool::<u8>::new().stats(), None);
+
+        let pool = Pool::builder().stats().build();
+        let value_a = pool.lend(|| 0);
+        let value_b = pool.lend(|| 1);
+        drop(value_a);
+        let value_a = pool.lend(|| 2);
+        let value_c = pool.lend(|| 3);
+        drop(value_c.freeze());
+
+        assert_eq!(pool.stats(), Some(PoolStats {
+            hits: 1,
+            misses: 3,
+            returns: 2,
+            returns_dropped: 0,
+            idle: 1,
+            idle_weight: 0,
+            outstanding: 2,
+            idle_high_water: 1,
+            outstanding_high_water: 3,
+        }));
+
+        // values returned after the last pool handle is gone are still kept idle
+        let pool_head = pool.inner.clone();
+        drop(pool);
+        drop(value_a);
+        drop(value_b);
+        let stats = pool_head.stats().unwrap();
+        assert_eq!((stats.returns, stats.returns_dropped), (4, 0));
+        assert_eq!((stats.idle, stats.outstanding), (3, 0));
+
+        let pool = Pool::builder().stats().return_filter(|value| *value == 0).build();
+        drop(pool.lend(|| 1));
+        assert_eq!(pool.stats().unwrap().returns_dropped, 1);
+    }
+
+    #[test]
+    fn hold_threshold() {
+        let clock = ManualClock::new();
+        let long_holds = Arc::new(Mutex::new(Vec::new()));
+        let pool = Pool::builder()
+            .clock(clock.clone())
+            .hold_threshold(Duration::from_millis(50))
+            .on_long_hold({
+                let long_holds = long_holds.clone();
+                move |value: &u8, held| long_holds.lock().unwrap().push((*value, held))
+            })
+            .hold_histogram()
+            .build();
+
+        let value_a = pool.lend(|| 0);
+        let value_b = pool.lend(|| 1).freeze();
+        clock.advance(Duration::from_millis(5));
+        drop(value_a);
+        clock.advance(Duration::from_secs(2));
+        drop(value_b);
+        drop(pool.lend(|| 2));
+        assert_eq!(*long_holds.lock().unwrap(), [(1, Duration::from_millis(2005))]);
+
+        let histogram = pool.hold_histogram().unwrap();
+        assert_eq!(histogram.total(), 3);
+        assert_eq!(histogram.buckets().collect::<Vec<_>>(), [
+            (Some(Duration::from_micros(10)), 1),
+            (Some(Duration::from_micros(100)), 0),
+            (Some(Duration::from_millis(1)), 0),
+            (Some(Duration::from_millis(10)), 1),
+            (Some(Duration::from_millis(100)), 0),
+            (Some(Duration::from_secs(1)), 0),
+            (Some(Duration::from_secs(10)), 1),
+            (None, 0),
+        ]);
+        assert_eq!(Pool::<u8>::new().hold_histogram(), None);
+
+        // nowhere to report long holds to, so lend times are not tracked
+        let pool = Pool::builder().hold_threshold(Duration::from_millis(50)).build();
+        assert_eq!(pool.lend(|| 0_u8).inner.lent_at.is_some(), cfg!(feature = "tracing"));
+    }
+
+    #[cfg(feature = "leak-detection")]
+    #[test]
+    fn outstanding_report() {
+        let clock = ManualClock::new();
+        let pool = Pool::builder().clock(clock.clone()).lend_backtraces().build();
+        let value_a = pool.lend(|| 0_u8).freeze();
+        clock.advance(Duration::from_secs(1));
+        let value_b = pool.lend(|| 1_u8);
+        let weak_a = value_a.downgrade();
+
+        let report = pool.outstanding_report();
+        assert_eq!(report.len(), 2);
+        assert_eq!(report[0].age, Duration::from_secs(1));
+        assert_eq!(report[1].age, Duration::ZERO);
+        assert!(report.iter().all(|handle| handle.lent_at.is_some()));
+
+        drop(value_b);
+        drop(value_a);
+        let value_a = weak_a.upgrade();
+        assert!(value_a.is_none());
+        assert!(pool.outstanding_report().is_empty());
+        drop(pool.lend(|| 2_u8));
+        assert!(pool.outstanding_report().is_empty());
+    }
+
+    #[cfg(feature = "leak-detection")]
+    #[test]
+    #[should_panic(expected = "dropped while 1 lent values are still out")]
+    fn on_leak_panic() {
+        let pool = Pool::builder().on_leak(super::LeakAction::Panic).build();
+        let _value = pool.lend(|| 0_u8);
+        let pool_cloned = pool.clone();
+        drop(pool);
+        drop(pool_cloned);
+    }
 }
@@ -0,0 +1,243 @@
+use std::{
+    io,
+    fs,
+    path::{
+        PathBuf,
+    },
+};
+
+use crate::{
+    registry,
+};
+
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
+pub enum PressureLevel {
+    Mild,
+    Moderate,
+    Critical,
+}
+
+/// How many idle values a pool keeps on `purge_all`.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Retention {
+    KeepAll,
+    KeepAtMost(usize),
+    /// Keeps the given percentage of idle values, rounded up.
+    KeepPercent(u8),
+}
+
+impl Retention {
+    pub(crate) fn idle_target(&self, idle: usize) -> Option<usize> {
+        match *self {
+            Retention::KeepAll =>
+                None,
+            Retention::KeepAtMost(idle_target) =>
+                Some(idle_target),
+            Retention::KeepPercent(percent) =>
+                Some((idle * percent.min(100) as usize).div_ceil(100)),
+        }
+    }
+}
+
+/// Per level retention of idle values for a pool, see `PoolBuilder::purge_policy`.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct PurgePolicy {
+    pub mild: Retention,
+    pub moderate: Retention,
+    pub critical: Retention,
+}
+
+impl Default for PurgePolicy {
+    fn default() -> PurgePolicy {
+        PurgePolicy {
+            mild: Retention::KeepPercent(50),
+            moderate: Retention::KeepPercent(10),
+            critical: Retention::KeepAtMost(0),
+        }
+    }
+}
+
+impl PurgePolicy {
+    /// Policy of a pool which is never purged.
+    pub fn opt_out() -> PurgePolicy {
+        PurgePolicy {
+            mild: Retention::KeepAll,
+            moderate: Retention::KeepAll,
+            critical: Retention::KeepAll,
+        }
+    }
+
+    pub fn retention(&self, level: PressureLevel) -> Retention {
+        match level {
+            PressureLevel::Mild =>
+                self.mild,
+            PressureLevel::Moderate =>
+                self.moderate,
+            PressureLevel::Critical =>
+                self.critical,
+        }
+    }
+}
+
+/// Trims idle values of every live pool according to its `PurgePolicy`, except for pools made with
+/// `PoolBuilder::build_local`. Use `PurgePolicy::opt_out` to keep a pool untouched. Returns the
+/// number of values dropped.
+pub fn purge_all(level: PressureLevel) -> usize {
+    registry::purge(level)
+}
+
+/// Source of memory pressure signals, polled by `poll_and_purge`.
+pub trait PressureSource {
+    /// Returns the level of pressure observed since the previous poll, if any.
+    fn poll(&mut self) -> io::Result<Option<PressureLevel>>;
+}
+
+/// Polls `source` once and purges pools if it reports pressure. Returns the number of values
+/// dropped.
+pub fn poll_and_purge<S>(source: &mut S) -> io::Result<usize> where S: PressureSource + ?Sized {
+    Ok(source.poll()?.map_or(0, purge_all))
+}
+
+/// Reads cgroup v2 `memory.events` file and reports pressure when its counters grow: `low` is
+/// mapped to mild, `high` to moderate, and `max`, `oom` or `oom_kill` to critical pressure.
+#[derive(Debug)]
+pub struct MemoryEventsPoller {
+    path: PathBuf,
+    counters: Option<MemoryEvents>,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Default, Debug)]
+struct MemoryEvents {
+    low: u64,
+    high: u64,
+    max: u64,
+    oom: u64,
+    oom_kill: u64,
+}
+
+impl MemoryEventsPoller {
+    pub fn new<P>(path: P) -> MemoryEventsPoller where P: Into<PathBuf> {
+        MemoryEventsPoller { path: path.into(), counters: None, }
+    }
+
+    fn read(&self) -> io::Result<MemoryEvents> {
+        let contents = fs::read_to_string(&self.path)?;
+        let mut events = MemoryEvents::default();
+        for line in contents.lines() {
+            let mut fields = line.split_whitespace();
+            let (Some(key), Some(value)) = (fields.next(), fields.next()) else {
+                continue;
+            };
+            let value = value.parse()
+                .map_err(|error| io::Error::new(io::ErrorKind::InvalidData, error))?;
+            match key {
+                "low" =>
+                    events.low = value,
+                "high" =>
+                    events.high = value,
+                "max" =>
+                    events.max = value,
+                "oom" =>
+                    events.oom = value,
+                "oom_kill" =>
+                    events.oom_kill = value,
+                _ =>
+                    (),
+            }
+        }
+        Ok(events)
+    }
+}
+
+impl PressureSource for MemoryEventsPoller {
+    fn poll(&mut self) -> io::Result<Option<PressureLevel>> {
+        let events = self.read()?;
+        // the first reading only sets the baseline
+        let Some(previous) = self.counters.replace(events) else {
+            return Ok(None);
+        };
+        let level = if events.max > previous.max || events.oom > previous.oom || events.oom_kill > previous.oom_kill {
+            Some(PressureLevel::Critical)
+        } else if events.high > previous.high {
+            Some(PressureLevel::Moderate)
+        } else if events.low > previous.low {
+            Some(PressureLevel::Mild)
+        } else {
+            None
+        };
+        Ok(level)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        fs,
+        process,
+    };
+
+    use super::{
+        PurgePolicy,
+        Retention,
+        PressureLevel,
+        PressureSource,
+        MemoryEventsPoller,
+    };
+
+    use crate::{
+        registry,
+        pool::Pool,
+    };
+
+    #[test]
+    fn memory_events_poller() {
+        let path = std::env::temp_dir()
+            .join(format!("alloc-pool-memory-events-{}", process::id()));
+        let write = |low, high, max| {
+            fs::write(&path, format!("low {low}\nhigh {high}\nmax {max}\noom 0\noom_kill 0\n")).unwrap();
+        };
+
+        let mut poller = MemoryEventsPoller::new(&path);
+        write(3, 0, 0);
+        assert_eq!(poller.poll().unwrap(), None);
+        assert_eq!(poller.poll().unwrap(), None);
+        write(4, 0, 0);
+        assert_eq!(poller.poll().unwrap(), Some(PressureLevel::Mild));
+        write(5, 1, 0);
+        assert_eq!(poller.poll().unwrap(), Some(PressureLevel::Moderate));
+        write(5, 1, 1);
+        assert_eq!(poller.poll().unwrap(), Some(PressureLevel::Critical));
+
+        fs::write(&path, "low x\n").unwrap();
+        assert!(poller.poll().is_err());
+        fs::remove_file(&path).unwrap();
+        assert!(poller.poll().is_err());
+    }
+
+    #[test]
+    fn purge_policy() {
+        let pool = Pool::builder().stats().purge_policy(PurgePolicy::default()).build();
+        let opted_out = Pool::builder().stats().purge_policy(PurgePolicy::opt_out()).build();
+        pool.prefill(5, || 0_u8);
+        opted_out.prefill(5, || 0_u8);
+
+        // other tests' pools are left alone
+        registry::purge_where(PressureLevel::Mild, |pool_id| pool_id == pool.id() || pool_id == opted_out.id());
+        assert_eq!(pool.stats().unwrap().idle, 3);
+        assert_eq!(opted_out.stats().unwrap().idle, 5);
+
+        // default pools are purged with the default policy, local ones are not reached
+        let default_pool = Pool::new();
+        let local = Pool::builder().stats().build_local();
+        default_pool.prefill(5, || 0_u8);
+        local.prefill(5, || 0_u8);
+        registry::purge_where(PressureLevel::Critical, |pool_id| pool_id == default_pool.id() || pool_id == local.id());
+        assert_eq!(default_pool.trim_to(0), 0);
+        assert_eq!(local.stats().unwrap().idle, 5);
+
+        let retention = PurgePolicy::default().retention(PressureLevel::Critical);
+        assert_eq!(retention, Retention::KeepAtMost(0));
+        assert_eq!(Retention::KeepPercent(10).idle_target(25), Some(3));
+        assert_eq!(Retention::KeepAll.idle_target(25), None);
+    }
+}
@@ -0,0 +1,57 @@
+use std::{
+    collections::{
+        HashMap,
+        HashSet,
+        BTreeMap,
+        VecDeque,
+        BinaryHeap,
+    },
+};
+
+/// Resets a value to a clean state before it goes back to the idle list of a recycling pool.
+/// Allocated memory is expected to be kept so the value can be reused without reallocation.
+pub trait Recycle {
+    fn recycle(&mut self);
+}
+
+impl<T> Recycle for Vec<T> {
+    fn recycle(&mut self) {
+        self.clear();
+    }
+}
+
+impl Recycle for String {
+    fn recycle(&mut self) {
+        self.clear();
+    }
+}
+
+impl<T> Recycle for VecDeque<T> {
+    fn recycle(&mut self) {
+        self.clear();
+    }
+}
+
+impl<K, V, S> Recycle for HashMap<K, V, S> {
+    fn recycle(&mut self) {
+        self.clear();
+    }
+}
+
+impl<T, S> Recycle for HashSet<T, S> {
+    fn recycle(&mut self) {
+        self.clear();
+    }
+}
+
+impl<K, V> Recycle for BTreeMap<K, V> {
+    fn recycle(&mut self) {
+        self.clear();
+    }
+}
+
+impl<T> Recycle for BinaryHeap<T> {
+    fn recycle(&mut self) {
+        self.clear();
+    }
+}
@@ -0,0 +1,155 @@
+use std::{
+    any,
+    sync::{
+        Arc,
+        Weak,
+        Mutex,
+        atomic::{
+            Ordering,
+        },
+    },
+};
+
+use crate::{
+    PoolHead,
+    budget::Budgeted,
+    pressure::PressureLevel,
+};
+
+/// Live named pool as seen by `snapshot`.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct PoolInfo {
+    pub name: String,
+    /// Type name of pooled values, as reported by `std::any::type_name`.
+    pub type_name: &'static str,
+    pub idle: usize,
+    pub outstanding: usize,
+}
+
+pub(crate) trait Registered: Send + Sync {
+    fn pool_id(&self) -> u64;
+
+    /// Same pool as seen by a `MemoryBudget`, without relying on trait object upcasting.
+    fn budgeted(self: Arc<Self>) -> Arc<dyn Budgeted>;
+
+    fn info(&self) -> Option<PoolInfo>;
+
+    fn purge(&self, level: PressureLevel) -> usize;
+}
+
+impl<T> Registered for PoolHead<T> where T: Send + Sync + 'static {
+    fn pool_id(&self) -> u64 {
+        self.id
+    }
+
+    fn budgeted(self: Arc<Self>) -> Arc<dyn Budgeted> {
+        self
+    }
+
+    fn info(&self) -> Option<PoolInfo> {
+        if self.handles.load(Ordering::SeqCst) == 0 {
+            return None;
+        }
+        let name = self.params.name.as_ref()?;
+        Some(PoolInfo {
+            name: name.clone(),
+            type_name: any::type_name::<T>(),
+            idle: self.idle.load(Ordering::Relaxed),
+            outstanding: self.slots.outstanding(),
+        })
+    }
+
+    fn purge(&self, level: PressureLevel) -> usize {
+        match self.params.purge_policy.retention(level).idle_target(self.idle.load(Ordering::Relaxed)) {
+            Some(idle_target) =>
+                self.trim_idle_to(idle_target),
+            None =>
+                0,
+        }
+    }
+}
+
+static REGISTRY: Mutex<Vec<Weak<dyn Registered>>> = Mutex::new(Vec::new());
+
+/// Made where `T` is known to be `Send + Sync`, see `Params::register`.
+pub(crate) fn downgrade<T>(pool_head: &Arc<PoolHead<T>>) -> Weak<dyn Registered> where T: Send + Sync + 'static {
+    let pool_head: Arc<dyn Registered> = pool_head.clone();
+    Arc::downgrade(&pool_head)
+}
+
+pub(crate) fn register(pool_head: Weak<dyn Registered>) {
+    let mut registry = REGISTRY.lock().unwrap();
+    registry.retain(|entry| entry.strong_count() > 0);
+    registry.push(pool_head);
+}
+
+fn live_pools() -> Vec<Arc<dyn Registered>> {
+    let mut registry = REGISTRY.lock().unwrap();
+    registry.retain(|entry| entry.strong_count() > 0);
+    registry.iter()
+        .filter_map(Weak::upgrade)
+        .collect()
+}
+
+/// Lists all live pools created with `Pool::named` or `BytesPool::named`, in registration order.
+pub fn snapshot() -> Vec<PoolInfo> {
+    live_pools().iter()
+        .filter_map(|pool_head| pool_head.info())
+        .collect()
+}
+
+/// Trims idle values of registered pools according to their `PurgePolicy`. Returns the number of
+/// values dropped.
+pub(crate) fn purge(level: PressureLevel) -> usize {
+    purge_where(level, |_pool_id| true)
+}
+
+/// Same as `purge`, but only for pools with ids accepted by `filter`.
+pub(crate) fn purge_where<F>(level: PressureLevel, filter: F) -> usize where F: Fn(u64) -> bool {
+    live_pools().iter()
+        .filter(|pool_head| filter(pool_head.pool_id()))
+        .map(|pool_head| pool_head.purge(level))
+        .sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        snapshot,
+        PoolInfo,
+    };
+
+    use crate::{
+        pool::Pool,
+        bytes::BytesPool,
+    };
+
+    fn find(name: &str) -> Option<PoolInfo> {
+        snapshot().into_iter()
+            .find(|info| info.name == name)
+    }
+
+    #[test]
+    fn named_pools() {
+        let pool = Pool::named("registry-test-frames");
+        let value = pool.lend(|| 0_u32);
+        drop(pool.lend(|| 1_u32));
+        assert_eq!(find("registry-test-frames"), Some(PoolInfo {
+            name: "registry-test-frames".to_string(),
+            type_name: "u32",
+            idle: 1,
+            outstanding: 1,
+        }));
+
+        let bytes_pool = BytesPool::named("registry-test-bytes");
+        let info = find("registry-test-bytes").unwrap();
+        assert_eq!(info.type_name, "alloc::vec::Vec<u8>");
+        assert_eq!((info.idle, info.outstanding), (0, 0));
+
+        drop(pool);
+        assert_eq!(find("registry-test-frames"), None);
+        drop(value);
+        drop(bytes_pool);
+        assert_eq!(find("registry-test-bytes"), None);
+    }
+}
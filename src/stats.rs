@@ -0,0 +1,76 @@
+use std::{
+    sync::{
+        atomic::{
+            Ordering,
+            AtomicU64,
+            AtomicUsize,
+        },
+    },
+};
+
+/// Snapshot of pool counters, see `PoolBuilder::stats`.
+#[derive(Clone, Copy, PartialEq, Eq, Default, Debug)]
+pub struct PoolStats {
+    /// Lends served from idle list.
+    pub hits: u64,
+    /// Lends which had to make a new value.
+    pub misses: u64,
+    /// Values returned back to the pool.
+    pub returns: u64,
+    /// Returned values dropped instead of being kept idle, for any reason: rejected by return
+    /// filter, idle list or memory budget is full, or the pool was detached.
+    pub returns_dropped: u64,
+    pub idle: usize,
+    /// Total weight of idle values, for `BytesPool` this is their capacity in bytes.
+    pub idle_weight: usize,
+    pub outstanding: usize,
+    pub idle_high_water: usize,
+    pub outstanding_high_water: usize,
+}
+
+#[derive(Default, Debug)]
+pub(crate) struct Counters {
+    hits: AtomicU64,
+    misses: AtomicU64,
+    returns: AtomicU64,
+    returns_dropped: AtomicU64,
+    idle_high_water: AtomicUsize,
+    outstanding_high_water: AtomicUsize,
+}
+
+impl Counters {
+    pub(crate) fn lent(&self, hit: bool, outstanding: usize) {
+        if hit {
+            self.hits.fetch_add(1, Ordering::Relaxed);
+        } else {
+            self.misses.fetch_add(1, Ordering::Relaxed);
+        }
+        self.outstanding_high_water.fetch_max(outstanding, Ordering::Relaxed);
+    }
+
+    pub(crate) fn returned(&self) {
+        self.returns.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn returned_dropped(&self) {
+        self.returns_dropped.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn idle_reserved(&self, idle: usize) {
+        self.idle_high_water.fetch_max(idle, Ordering::Relaxed);
+    }
+
+    pub(crate) fn snapshot(&self, idle: usize, idle_weight: usize, outstanding: usize) -> PoolStats {
+        PoolStats {
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+            returns: self.returns.load(Ordering::Relaxed),
+            returns_dropped: self.returns_dropped.load(Ordering::Relaxed),
+            idle,
+            idle_weight,
+            outstanding,
+            idle_high_water: self.idle_high_water.load(Ordering::Relaxed),
+            outstanding_high_water: self.outstanding_high_water.load(Ordering::Relaxed),
+        }
+    }
+}
@@ -0,0 +1,258 @@
+use std::{
+    thread,
+    pin::{
+        Pin,
+    },
+    future::{
+        Future,
+    },
+    task::{
+        Poll,
+        Waker,
+        Context,
+    },
+    collections::{
+        VecDeque,
+    },
+    sync::{
+        Arc,
+        Mutex,
+        atomic::{
+            Ordering,
+            AtomicBool,
+            AtomicUsize,
+        },
+    },
+    time::{
+        Duration,
+        Instant,
+    },
+};
+
+#[derive(Debug)]
+pub(crate) struct Slots {
+    outstanding: AtomicUsize,
+    max_outstanding: Option<usize>,
+    waiters: Mutex<VecDeque<Arc<Waiter>>>,
+}
+
+#[derive(Debug)]
+struct Waiter {
+    granted: AtomicBool,
+    wake: Wake,
+}
+
+#[derive(Debug)]
+enum Wake {
+    Thread(thread::Thread),
+    Task(Mutex<Waker>),
+}
+
+impl Waiter {
+    fn new(wake: Wake) -> Arc<Waiter> {
+        Arc::new(Waiter { granted: AtomicBool::new(false), wake, })
+    }
+
+    fn is_granted(&self) -> bool {
+        self.granted.load(Ordering::Acquire)
+    }
+
+    fn grant(&self) {
+        self.granted.store(true, Ordering::Release);
+        match &self.wake {
+            Wake::Thread(thread) =>
+                thread.unpark(),
+            Wake::Task(waker) =>
+                waker.lock().unwrap().wake_by_ref(),
+        }
+    }
+}
+
+impl Slots {
+    pub(crate) fn new(max_outstanding: Option<usize>) -> Slots {
+        Slots {
+            outstanding: AtomicUsize::new(0),
+            max_outstanding,
+            waiters: Mutex::new(VecDeque::new()),
+        }
+    }
+
+    pub(crate) fn outstanding(&self) -> usize {
+        self.outstanding.load(Ordering::Relaxed)
+    }
+
+    pub(crate) fn try_acquire(&self) -> bool {
+        match self.max_outstanding {
+            None => {
+                self.outstanding.fetch_add(1, Ordering::Relaxed);
+                true
+            },
+            Some(max_outstanding) =>
+                self.outstanding
+                    .fetch_update(Ordering::AcqRel, Ordering::Relaxed, |count| if count < max_outstanding { Some(count + 1) } else { None })
+                    .is_ok(),
+        }
+    }
+
+    /// Takes a slot even if `max_outstanding` is reached, for values which must be lent without
+    /// waiting.
+    pub(crate) fn force_acquire(&self) {
+        self.outstanding.fetch_add(1, Ordering::AcqRel);
+    }
+
+    /// Waits for a free slot, at most `timeout` if given. Slots are handed over to waiters
+    /// in the order they have arrived.
+    pub(crate) fn acquire(&self, timeout: Option<Duration>) -> bool {
+        if self.try_acquire() {
+            return true;
+        }
+
+        let deadline = timeout.map(|timeout| Instant::now() + timeout);
+        let waiter = {
+            let mut waiters = self.waiters.lock().unwrap();
+            // retry under lock so a concurrent release cannot slip by unnoticed
+            if self.try_acquire() {
+                return true;
+            }
+            let waiter = Waiter::new(Wake::Thread(thread::current()));
+            waiters.push_back(waiter.clone());
+            waiter
+        };
+
+        loop {
+            if waiter.is_granted() {
+                return true;
+            }
+            match deadline {
+                None =>
+                    thread::park(),
+                Some(deadline) => {
+                    let now = Instant::now();
+                    if now >= deadline {
+                        let mut waiters = self.waiters.lock().unwrap();
+                        if waiter.is_granted() {
+                            return true;
+                        }
+                        waiters.retain(|other| !Arc::ptr_eq(other, &waiter));
+                        return false;
+                    }
+                    thread::park_timeout(deadline - now);
+                },
+            }
+        }
+    }
+
+    /// Same as `acquire` without timeout, but parks the task instead of the thread.
+    pub(crate) fn acquire_async(&self) -> Acquire<'_> {
+        Acquire { slots: self, state: AcquireState::Init, }
+    }
+
+    pub(crate) fn release(&self) {
+        if self.max_outstanding.is_none() {
+            self.outstanding.fetch_sub(1, Ordering::Relaxed);
+            return;
+        }
+
+        let mut waiters = self.waiters.lock().unwrap();
+        match waiters.pop_front() {
+            Some(waiter) =>
+                // hand the slot over directly, outstanding count stays the same
+                waiter.grant(),
+            None => {
+                self.outstanding.fetch_sub(1, Ordering::AcqRel);
+            },
+        }
+    }
+}
+
+pub(crate) struct Acquire<'a> {
+    slots: &'a Slots,
+    state: AcquireState,
+}
+
+enum AcquireState {
+    Init,
+    Waiting(Arc<Waiter>),
+    Done,
+}
+
+impl Future for Acquire<'_> {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        let this = &mut *self;
+        match &this.state {
+            AcquireState::Init => {
+                if this.slots.try_acquire() {
+                    this.state = AcquireState::Done;
+                    return Poll::Ready(());
+                }
+                let mut waiters = this.slots.waiters.lock().unwrap();
+                if this.slots.try_acquire() {
+                    drop(waiters);
+                    this.state = AcquireState::Done;
+                    return Poll::Ready(());
+                }
+                let waiter = Waiter::new(Wake::Task(Mutex::new(cx.waker().clone())));
+                waiters.push_back(waiter.clone());
+                drop(waiters);
+                this.state = AcquireState::Waiting(waiter);
+                Poll::Pending
+            },
+            AcquireState::Waiting(waiter) => {
+                if let Wake::Task(waker) = &waiter.wake {
+                    let mut waker = waker.lock().unwrap();
+                    if !waker.will_wake(cx.waker()) {
+                        *waker = cx.waker().clone();
+                    }
+                }
+                // checked after waker update so a concurrent grant is not missed
+                if waiter.is_granted() {
+                    this.state = AcquireState::Done;
+                    Poll::Ready(())
+                } else {
+                    Poll::Pending
+                }
+            },
+            AcquireState::Done =>
+                panic!("Acquire future polled after completion"),
+        }
+    }
+}
+
+impl Drop for Acquire<'_> {
+    fn drop(&mut self) {
+        if let AcquireState::Waiting(waiter) = &self.state {
+            let mut waiters = self.slots.waiters.lock().unwrap();
+            if waiter.is_granted() {
+                // slot has been handed over already: pass it on to the next waiter
+                drop(waiters);
+                self.slots.release();
+            } else {
+                waiters.retain(|other| !Arc::ptr_eq(other, waiter));
+            }
+        }
+    }
+}
+
+pub(crate) struct SlotGuard<'a> {
+    slots: Option<&'a Slots>,
+}
+
+impl<'a> SlotGuard<'a> {
+    pub(crate) fn new(slots: &'a Slots) -> SlotGuard<'a> {
+        SlotGuard { slots: Some(slots), }
+    }
+
+    pub(crate) fn disarm(mut self) {
+        self.slots = None;
+    }
+}
+
+impl Drop for SlotGuard<'_> {
+    fn drop(&mut self) {
+        if let Some(slots) = self.slots.take() {
+            slots.release();
+        }
+    }
+}